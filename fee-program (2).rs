@@ -4,6 +4,8 @@ use anchor_spl::token::{self, Token, TokenAccount, Transfer};
 
 declare_id!("FeeCLPnVdK5QyGj8KLEXfCgPZR4uNJE94a4Xu2B"); // Replace with your program ID
 
+pub const MAX_REFERRAL_SHARE_BPS: u16 = 5_000; // Referrers earn at most half of the swap fee
+
 #[program]
 pub mod openfund_fee_management {
     use super::*;
@@ -53,6 +55,64 @@ pub mod openfund_fee_management {
         Ok(())
     }
 
+    pub fn set_referral_share(
+        ctx: Context<SetReferralShare>,
+        referral_share_bps: u16,
+    ) -> Result<()> {
+        require!(
+            referral_share_bps <= MAX_REFERRAL_SHARE_BPS,
+            ErrorCode::ReferralShareTooHigh
+        );
+
+        let fee_config = &mut ctx.accounts.fee_config;
+        fee_config.referral_share_bps = referral_share_bps;
+
+        Ok(())
+    }
+
+    // Grows a fee config created before the referral share existed to the current layout.
+    // The new bytes are zeroed, so referrals stay off until `set_referral_share` is called
+    pub fn migrate_fee_config(ctx: Context<MigrateFeeConfig>) -> Result<()> {
+        let fee_config = ctx.accounts.fee_config.to_account_info();
+        let new_len = 8 + std::mem::size_of::<FeeConfig>();
+        if fee_config.data_len() >= new_len {
+            return Ok(());
+        }
+
+        // The account cannot be deserialized yet, so check it by hand: the authority is the
+        // first field after the discriminator in every layout
+        {
+            let data = fee_config.try_borrow_data()?;
+            require!(
+                data.len() >= 40 && data[..8] == FeeConfig::discriminator(),
+                ErrorCode::InvalidFeeConfig
+            );
+            require!(
+                data[8..40] == ctx.accounts.authority.key().to_bytes(),
+                ErrorCode::InvalidFeeConfig
+            );
+        }
+
+        // Top up the rent for the larger account before growing it
+        let rent = Rent::get()?.minimum_balance(new_len);
+        let shortfall = rent.saturating_sub(fee_config.lamports());
+        if shortfall > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.authority.to_account_info(),
+                        to: fee_config.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        fee_config.realloc(new_len, true)?;
+
+        Ok(())
+    }
+
     pub fn update_treasury(
         ctx: Context<UpdateTreasury>,
     ) -> Result<()> {
@@ -140,6 +200,8 @@ pub struct FeeConfig {
     pub lp_fee_pct: u16,           // Percentage of fee going to liquidity providers
     pub protocol_treasury: Pubkey, // Treasury account to collect protocol fees
     pub bump: u8,                  // PDA bump seed
+    // Fields below were added later, configs created before them need `migrate_fee_config`
+    pub referral_share_bps: u16,   // Share of the swap fee paid to referrers, in bps of the fee
 }
 
 #[derive(Accounts)]
@@ -176,6 +238,37 @@ pub struct UpdateFeeConfig<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct SetReferralShare<'info> {
+    #[account(
+        mut,
+        seeds = [b"fee_config".as_ref()],
+        bump = fee_config.bump,
+        constraint = fee_config.authority == authority.key()
+    )]
+    pub fee_config: Account<'info, FeeConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateFeeConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"fee_config".as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    /// CHECK: Still in the old layout, checked against the discriminator and authority
+    pub fee_config: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct UpdateTreasury<'info> {
     #[account(
@@ -246,4 +339,8 @@ pub enum ErrorCode {
     FeeTooHigh,
     #[msg("Fee distribution percentages must add up to 100%")]
     FeeDistributionInvalid,
+    #[msg("Referral share is too high")]
+    ReferralShareTooHigh,
+    #[msg("Fee config account is not a fee config owned by this authority")]
+    InvalidFeeConfig,
 }
@@ -50,79 +50,96 @@ pub mod openfund_oracle {
     }
 
     pub fn get_price(ctx: Context<GetPrice>) -> Result<PriceData> {
-        let oracle_config = &ctx.accounts.oracle_config;
         let clock = Clock::get()?;
-        
-        match oracle_config.price_feed_type {
-            OracleFeedType::Pyth => {
-                // Get price from Pyth
-                let price_feed: PriceFeed = load_price_feed_from_account_info(
-                    &ctx.accounts.price_feed
-                ).map_err(|_| ErrorCode::PriceUnavailable)?;
-                
-                let price_data = price_feed.get_price_unchecked();
-                
-                // Verify freshness
-                let last_update_time = price_data.publish_time;
-                let time_since_update = clock.unix_timestamp - last_update_time;
-                require!(
-                    time_since_update <= oracle_config.heartbeat_threshold_seconds,
-                    ErrorCode::StalePrice
-                );
-                
-                // Verify confidence
-                let confidence_ratio = (price_data.conf as f64 / price_data.price as f64) * 100.0;
-                require!(
-                    confidence_ratio <= oracle_config.confidence_threshold_percent as f64,
-                    ErrorCode::LowConfidence
-                );
-                
-                // Return price data
-                Ok(PriceData {
-                    price: price_data.price,
-                    confidence: price_data.conf,
-                    exponent: price_data.expo,
-                    last_updated: price_data.publish_time,
-                })
-            },
-            OracleFeedType::Switchboard => {
-                // Get price from Switchboard
-                let aggregator = AggregatorAccountData::new(ctx.accounts.price_feed.clone())
-                    .map_err(|_| ErrorCode::PriceUnavailable)?;
-                
-                let latest_result = aggregator.get_result()
-                    .map_err(|_| ErrorCode::PriceUnavailable)?;
-                
-                // Verify freshness
-                let last_update_time = aggregator.latest_confirmed_round.unwrap().round_open_timestamp;
-                let time_since_update = clock.unix_timestamp - (last_update_time as i64);
-                require!(
-                    time_since_update <= oracle_config.heartbeat_threshold_seconds,
-                    ErrorCode::StalePrice
-                );
-                
-                // Get confidence interval
-                let latest_confidence_interval = aggregator.latest_confidence_interval().unwrap();
-                let confidence_ratio = (latest_confidence_interval.to_f64() / latest_result.to_f64()) * 100.0;
-                require!(
-                    confidence_ratio <= oracle_config.confidence_threshold_percent as f64,
-                    ErrorCode::LowConfidence
-                );
-                
-                // Return price data
-                let decimal = aggregator.latest_value().unwrap();
-                Ok(PriceData {
-                    price: decimal.mantissa,
-                    confidence: latest_confidence_interval.mantissa,
-                    exponent: decimal.scale as i32,
-                    last_updated: last_update_time as i64,
-                })
-            },
-            OracleFeedType::Chainlink => {
-                // Chainlink implementation would go here
-                // For simplicity, we're using a placeholder
-                return Err(ErrorCode::UnsupportedOracle.into());
-            }
+        read_price(
+            &ctx.accounts.oracle_config,
+            &ctx.accounts.price_feed,
+            clock.unix_timestamp,
+        )
+    }
+}
+
+// Reads and validates the configured feed, usable by other programs that read the oracle
+// accounts directly and need to handle a stale price themselves
+pub fn read_price(
+    oracle_config: &OracleConfig,
+    price_feed: &AccountInfo,
+    now: i64,
+) -> Result<PriceData> {
+    match oracle_config.price_feed_type {
+        OracleFeedType::Pyth => {
+            // Get price from Pyth
+            let price_feed: PriceFeed = load_price_feed_from_account_info(
+                price_feed
+            ).map_err(|_| ErrorCode::PriceUnavailable)?;
+
+            let price_data = price_feed.get_price_unchecked();
+
+            // Verify freshness
+            let last_update_time = price_data.publish_time;
+            let time_since_update = now - last_update_time;
+            require!(
+                time_since_update <= oracle_config.heartbeat_threshold_seconds,
+                ErrorCode::StalePrice
+            );
+
+            // Verify confidence
+            let confidence_ratio = (price_data.conf as f64 / price_data.price as f64) * 100.0;
+            require!(
+                confidence_ratio <= oracle_config.confidence_threshold_percent as f64,
+                ErrorCode::LowConfidence
+            );
+
+            // Return price data
+            Ok(PriceData {
+                price: price_data.price,
+                confidence: price_data.conf,
+                exponent: price_data.expo,
+                last_updated: price_data.publish_time,
+            })
+        },
+        OracleFeedType::Switchboard => {
+            // Get price from Switchboard
+            let aggregator = AggregatorAccountData::new(price_feed.clone())
+                .map_err(|_| ErrorCode::PriceUnavailable)?;
+
+            let latest_result = aggregator.get_result()
+                .map_err(|_| ErrorCode::PriceUnavailable)?;
+
+            // Verify freshness
+            let last_update_time = aggregator
+                .latest_confirmed_round
+                .ok_or(ErrorCode::PriceUnavailable)?
+                .round_open_timestamp;
+            let time_since_update = now - (last_update_time as i64);
+            require!(
+                time_since_update <= oracle_config.heartbeat_threshold_seconds,
+                ErrorCode::StalePrice
+            );
+
+            // Get confidence interval
+            let latest_confidence_interval = aggregator.latest_confidence_interval()
+                .map_err(|_| ErrorCode::PriceUnavailable)?;
+            let confidence_ratio = (latest_confidence_interval.to_f64() / latest_result.to_f64()) * 100.0;
+            require!(
+                confidence_ratio <= oracle_config.confidence_threshold_percent as f64,
+                ErrorCode::LowConfidence
+            );
+
+            // Return price data
+            let decimal = aggregator.latest_value()
+                .map_err(|_| ErrorCode::PriceUnavailable)?;
+            Ok(PriceData {
+                price: decimal.mantissa,
+                confidence: latest_confidence_interval.mantissa,
+                exponent: decimal.scale as i32,
+                last_updated: last_update_time as i64,
+            })
+        },
+        OracleFeedType::Chainlink => {
+            // Chainlink implementation would go here
+            // For simplicity, we're using a placeholder
+            return Err(ErrorCode::UnsupportedOracle.into());
         }
     }
 }
@@ -120,6 +120,16 @@ pub mod openfund_settlement {
             amount_in,
         )?;
         
+        emit!(OrderCreated {
+            order: order.key(),
+            user: order.user,
+            pool: order.pool,
+            direction,
+            amount_in,
+            min_amount_out,
+            expiry_timestamp,
+        });
+
         Ok(())
     }
 
@@ -163,6 +173,13 @@ pub mod openfund_settlement {
             order.amount_in,
         )?;
         
+        emit!(OrderCancelled {
+            order: order.key(),
+            user: order.user,
+            pool: order.pool,
+            amount_refunded: order.amount_in,
+        });
+
         Ok(())
     }
 
@@ -279,6 +296,17 @@ pub mod openfund_settlement {
             )?;
         }
         
+        emit!(OrderExecuted {
+            order: order.key(),
+            user: order.user,
+            pool: order.pool,
+            direction: order.direction,
+            amount_in: order.amount_in,
+            amount_out,
+            fee_amount,
+            executed_at: order.executed_at,
+        });
+
         Ok(())
     }
 
@@ -355,6 +383,37 @@ pub struct Order {
     pub bump: u8,                             // PDA bump seed
 }
 
+#[event]
+pub struct OrderCreated {
+    pub order: Pubkey,
+    pub user: Pubkey,
+    pub pool: Pubkey,
+    pub direction: OrderDirection,
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+    pub expiry_timestamp: i64,
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub order: Pubkey,
+    pub user: Pubkey,
+    pub pool: Pubkey,
+    pub amount_refunded: u64,
+}
+
+#[event]
+pub struct OrderExecuted {
+    pub order: Pubkey,
+    pub user: Pubkey,
+    pub pool: Pubkey,
+    pub direction: OrderDirection,
+    pub amount_in: u64,
+    pub amount_out: u64,                      // Gross output, including the settlement fee
+    pub fee_amount: u64,
+    pub executed_at: i64,
+}
+
 #[derive(Accounts)]
 pub struct InitializeSettlementManager<'info> {
     #[account(
@@ -1,32 +1,605 @@
 // Core AMM DEX contract for Solana using Anchor framework
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::token::{spl_token::native_mint, Token};
+use anchor_spl::token_2022::Token2022;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use openfund_fee_management::FeeConfig;
+use openfund_oracle::OracleConfig;
+use spl_token_2022::extension::{
+    transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+};
+use std::cell::RefMut;
 use std::ops::Div;
+use uint::construct_uint;
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS"); // Replace with your program ID
 
+construct_uint! {
+    pub struct U256(4);
+}
+
+// StableSwap amplification bounds and ramping limits
+pub const MIN_AMP: u64 = 1;
+pub const MAX_AMP: u64 = 1_000_000;
+pub const MAX_AMP_CHANGE: u64 = 10;          // Max factor the amp may move by in one ramp
+pub const MIN_RAMP_DURATION: i64 = 86_400;   // Min ramp length and min gap between ramps (1 day)
+
+// Weighted pool weights are normalized so that both sides sum to WEIGHT_ONE
+pub const WEIGHT_ONE: u64 = 1_000_000;
+pub const MIN_WEIGHT: u64 = 10_000;          // Each side holds at least 1% of the pool value
+
+// PMM slippage parameter k is scaled by PMM_K_ONE, which trades like constant product.
+// Smaller values keep prices closer to the oracle
+pub const PMM_K_ONE: u64 = 1_000_000;
+
+// Concentrated liquidity tick bounds, sqrt prices are Q64.64 fixed point
+pub const MIN_TICK: i32 = -443_636;
+pub const MAX_TICK: i32 = 443_636;
+pub const MIN_SQRT_PRICE_X64: u128 = 4_295_048_017;
+pub const MAX_SQRT_PRICE_X64: u128 = 79_226_673_515_401_279_992_447_579_062;
+pub const MAX_TICK_SPACING: u16 = 16_384;
+pub const TICK_ARRAY_SIZE: usize = 32;       // Initializable ticks stored per tick array
+
+// Multi-hop routing limits
+pub const MAX_ROUTE_HOPS: usize = 4;
+pub const ROUTE_ACCOUNTS_PER_HOP: usize = 7;
+
+// Observation buffers must fit in an account created through CPI (10 KiB)
+pub const MAX_OBSERVATION_CAPACITY: u16 = 240;
+
+// Fee tiers are expressed in basis points of the swap input
+pub const FEE_TIER_DENOMINATOR: u64 = 10_000;
+pub const MAX_FEE_TIERS: usize = 16;
+pub const DEFAULT_FEE_TIERS_BPS: [u16; 4] = [1, 5, 30, 100];
+
+// Price guards trip on relative moves of at most 100%
+pub const MAX_PRICE_GUARD_BPS: u16 = 10_000;
+
+// Pool registry pages must fit in an account created through CPI (10 KiB)
+pub const POOLS_PER_REGISTRY_PAGE: usize = 256;
+
+// LP tokens locked forever on the first deposit, so the LP supply never returns to zero
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+// Size of an SPL Token account, used for the temporary wSOL accounts
+pub const TOKEN_ACCOUNT_LEN: u64 = 165;
+
+// Multi-asset pool bounds. Deposits and withdrawals touching every member pass
+// [mint, vault, user_token_account] for each of them, in the pool's mint order
+pub const MIN_MULTI_POOL_TOKENS: usize = 3;
+pub const MAX_MULTI_POOL_TOKENS: usize = 8;
+pub const MULTI_POOL_ACCOUNTS_PER_TOKEN: usize = 3;
+
 #[program]
 pub mod openfund_dex {
     use super::*;
 
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
-        fee_numerator: u64,
-        fee_denominator: u64,
+        fee_tier_bps: u16,
+        min_initial_deposit_a: u64,
+        min_initial_deposit_b: u64,
     ) -> Result<()> {
-        require!(fee_denominator > 0, ErrorCode::InvalidFee);
-        require!(fee_numerator < fee_denominator, ErrorCode::InvalidFee);
+        let bump = *ctx.bumps.get("pool").unwrap();
+        let authority_bump = *ctx.bumps.get("pool_authority").unwrap();
+        write_pool_state(
+            ctx.accounts,
+            bump,
+            authority_bump,
+            fee_tier_bps,
+            PoolType::ConstantProduct,
+        )?;
 
         let pool = &mut ctx.accounts.pool;
-        pool.token_a_mint = ctx.accounts.token_a_mint.key();
-        pool.token_b_mint = ctx.accounts.token_b_mint.key();
-        pool.token_a_account = ctx.accounts.token_a_account.key();
-        pool.token_b_account = ctx.accounts.token_b_account.key();
-        pool.lp_mint = ctx.accounts.lp_mint.key();
-        pool.authority = ctx.accounts.authority.key();
-        pool.fee_numerator = fee_numerator;
-        pool.fee_denominator = fee_denominator;
-        pool.bump = *ctx.bumps.get("pool").unwrap();
+        pool.min_initial_deposit_a = min_initial_deposit_a;
+        pool.min_initial_deposit_b = min_initial_deposit_b;
+
+        Ok(())
+    }
+
+    pub fn initialize_stable_pool(
+        ctx: Context<InitializePool>,
+        fee_tier_bps: u16,
+        amp: u64,
+        min_initial_deposit_a: u64,
+        min_initial_deposit_b: u64,
+    ) -> Result<()> {
+        require!(amp >= MIN_AMP && amp <= MAX_AMP, ErrorCode::InvalidAmp);
+        // The invariant compares raw balances, so both sides must use the same decimals
+        require!(
+            ctx.accounts.token_a_mint.decimals == ctx.accounts.token_b_mint.decimals,
+            ErrorCode::DecimalsMismatch
+        );
+
+        let bump = *ctx.bumps.get("pool").unwrap();
+        let authority_bump = *ctx.bumps.get("pool_authority").unwrap();
+        write_pool_state(
+            ctx.accounts,
+            bump,
+            authority_bump,
+            fee_tier_bps,
+            PoolType::StableSwap,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.initial_amp = amp;
+        pool.target_amp = amp;
+        pool.min_initial_deposit_a = min_initial_deposit_a;
+        pool.min_initial_deposit_b = min_initial_deposit_b;
+
+        Ok(())
+    }
+
+    pub fn ramp_amp(
+        ctx: Context<RampAmp>,
+        target_amp: u64,
+        stop_ts: i64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.pool_type == PoolType::StableSwap, ErrorCode::InvalidPoolType);
+        require!(target_amp >= MIN_AMP && target_amp <= MAX_AMP, ErrorCode::InvalidAmp);
+
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        require!(
+            now >= pool.ramp_start_ts.checked_add(MIN_RAMP_DURATION).unwrap(),
+            ErrorCode::RampLocked
+        );
+        require!(
+            stop_ts >= now.checked_add(MIN_RAMP_DURATION).unwrap(),
+            ErrorCode::InvalidRamp
+        );
+
+        // Limit how far a single ramp can move the amplification coefficient
+        let current_amp = pool.current_amp(now);
+        if target_amp > current_amp {
+            require!(
+                target_amp <= current_amp.checked_mul(MAX_AMP_CHANGE).unwrap(),
+                ErrorCode::InvalidRamp
+            );
+        } else {
+            require!(
+                target_amp.checked_mul(MAX_AMP_CHANGE).unwrap() >= current_amp,
+                ErrorCode::InvalidRamp
+            );
+        }
+
+        pool.initial_amp = current_amp;
+        pool.target_amp = target_amp;
+        pool.ramp_start_ts = now;
+        pool.ramp_stop_ts = stop_ts;
+
+        Ok(())
+    }
+
+    pub fn stop_ramp_amp(ctx: Context<StopRampAmp>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.pool_type == PoolType::StableSwap, ErrorCode::InvalidPoolType);
+
+        let clock = Clock::get()?;
+        let current_amp = pool.current_amp(clock.unix_timestamp);
+
+        // Freeze the amp at its current value
+        pool.initial_amp = current_amp;
+        pool.target_amp = current_amp;
+        pool.ramp_start_ts = clock.unix_timestamp;
+        pool.ramp_stop_ts = clock.unix_timestamp;
+
+        Ok(())
+    }
+
+    pub fn initialize_weighted_pool(
+        ctx: Context<InitializePool>,
+        fee_tier_bps: u16,
+        weight_a: u64,
+        weight_b: u64,
+        min_initial_deposit_a: u64,
+        min_initial_deposit_b: u64,
+    ) -> Result<()> {
+        let (weight_a, weight_b) =
+            normalize_weights(weight_a, weight_b).ok_or(ErrorCode::InvalidWeights)?;
+
+        let bump = *ctx.bumps.get("pool").unwrap();
+        let authority_bump = *ctx.bumps.get("pool_authority").unwrap();
+        write_pool_state(
+            ctx.accounts,
+            bump,
+            authority_bump,
+            fee_tier_bps,
+            PoolType::Weighted,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.weight_a = weight_a;
+        pool.weight_b = weight_b;
+        pool.end_weight_a = weight_a;
+        pool.end_weight_b = weight_b;
+        pool.min_initial_deposit_a = min_initial_deposit_a;
+        pool.min_initial_deposit_b = min_initial_deposit_b;
+
+        Ok(())
+    }
+
+    pub fn initialize_liquidity_bootstrapping_pool(
+        ctx: Context<InitializePool>,
+        fee_tier_bps: u16,
+        params: LiquidityBootstrappingParams,
+    ) -> Result<()> {
+        let (start_weight_a, start_weight_b) =
+            normalize_weights(params.start_weight_a, params.start_weight_b)
+                .ok_or(ErrorCode::InvalidWeights)?;
+        let (end_weight_a, end_weight_b) =
+            normalize_weights(params.end_weight_a, params.end_weight_b)
+                .ok_or(ErrorCode::InvalidWeights)?;
+        require!(
+            params.start_ts < params.end_ts && params.end_ts > Clock::get()?.unix_timestamp,
+            ErrorCode::InvalidWeightSchedule
+        );
+
+        let bump = *ctx.bumps.get("pool").unwrap();
+        let authority_bump = *ctx.bumps.get("pool_authority").unwrap();
+        write_pool_state(
+            ctx.accounts,
+            bump,
+            authority_bump,
+            fee_tier_bps,
+            PoolType::Weighted,
+        )?;
+
+        // Weights move from the start to the end weights over the sale, and only the
+        // authority can add or remove liquidity until it ends
+        let pool = &mut ctx.accounts.pool;
+        pool.weight_a = start_weight_a;
+        pool.weight_b = start_weight_b;
+        pool.end_weight_a = end_weight_a;
+        pool.end_weight_b = end_weight_b;
+        pool.weight_start_ts = params.start_ts;
+        pool.weight_end_ts = params.end_ts;
+        pool.liquidity_bootstrapping = true;
+        pool.min_initial_deposit_a = params.min_initial_deposit_a;
+        pool.min_initial_deposit_b = params.min_initial_deposit_b;
+
+        Ok(())
+    }
+
+    pub fn initialize_pmm_pool(
+        ctx: Context<InitializePool>,
+        fee_tier_bps: u16,
+        k: u64,
+        min_initial_deposit_a: u64,
+        min_initial_deposit_b: u64,
+    ) -> Result<()> {
+        require!(k > 0 && k <= PMM_K_ONE, ErrorCode::InvalidPmmK);
+
+        let bump = *ctx.bumps.get("pool").unwrap();
+        let authority_bump = *ctx.bumps.get("pool_authority").unwrap();
+        write_pool_state(
+            ctx.accounts,
+            bump,
+            authority_bump,
+            fee_tier_bps,
+            PoolType::Pmm,
+        )?;
+
+        // Swaps are priced on the reserves alone until an oracle is linked with `set_oracle_guard`
+        let pool = &mut ctx.accounts.pool;
+        pool.pmm_k = k;
+        pool.min_initial_deposit_a = min_initial_deposit_a;
+        pool.min_initial_deposit_b = min_initial_deposit_b;
+
+        Ok(())
+    }
+
+    pub fn initialize_concentrated_pool(
+        ctx: Context<InitializePool>,
+        fee_tier_bps: u16,
+        tick_spacing: u16,
+        initial_sqrt_price_x64: u128,
+    ) -> Result<()> {
+        require!(
+            tick_spacing > 0 && tick_spacing <= MAX_TICK_SPACING,
+            ErrorCode::InvalidTickSpacing
+        );
+        require!(
+            initial_sqrt_price_x64 >= MIN_SQRT_PRICE_X64
+                && initial_sqrt_price_x64 < MAX_SQRT_PRICE_X64,
+            ErrorCode::InvalidSqrtPrice
+        );
+
+        let bump = *ctx.bumps.get("pool").unwrap();
+        let authority_bump = *ctx.bumps.get("pool_authority").unwrap();
+        write_pool_state(
+            ctx.accounts,
+            bump,
+            authority_bump,
+            fee_tier_bps,
+            PoolType::Concentrated,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.tick_spacing = tick_spacing;
+        pool.sqrt_price_x64 = initial_sqrt_price_x64;
+        pool.tick_current = tick_at_sqrt_price(initial_sqrt_price_x64);
+
+        Ok(())
+    }
+
+    pub fn initialize_tick_array(
+        ctx: Context<InitializeTickArray>,
+        start_tick_index: i32,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(pool.pool_type == PoolType::Concentrated, ErrorCode::InvalidPoolType);
+
+        // Tick arrays tile the tick range without overlapping
+        let ticks_per_array = pool.tick_spacing as i32 * TICK_ARRAY_SIZE as i32;
+        require!(
+            start_tick_index.rem_euclid(ticks_per_array) == 0,
+            ErrorCode::InvalidTickArray
+        );
+        require!(
+            start_tick_index + ticks_per_array > MIN_TICK && start_tick_index <= MAX_TICK,
+            ErrorCode::InvalidTickArray
+        );
+
+        let mut tick_array = ctx.accounts.tick_array.load_init()?;
+        tick_array.pool = pool.key();
+        tick_array.start_tick_index = start_tick_index;
+
+        Ok(())
+    }
+
+    pub fn open_position(
+        ctx: Context<OpenPosition>,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(pool.pool_type == PoolType::Concentrated, ErrorCode::InvalidPoolType);
+
+        let tick_spacing = pool.tick_spacing as i32;
+        require!(tick_lower < tick_upper, ErrorCode::InvalidTickRange);
+        require!(
+            tick_lower >= MIN_TICK && tick_upper <= MAX_TICK,
+            ErrorCode::InvalidTickRange
+        );
+        require!(
+            tick_lower % tick_spacing == 0 && tick_upper % tick_spacing == 0,
+            ErrorCode::InvalidTickRange
+        );
+
+        let position = &mut ctx.accounts.position;
+        position.pool = pool.key();
+        position.owner = ctx.accounts.owner.key();
+        position.tick_lower = tick_lower;
+        position.tick_upper = tick_upper;
+        position.liquidity = 0;
+        position.fee_growth_inside_a_last_x64 = 0;
+        position.fee_growth_inside_b_last_x64 = 0;
+        position.tokens_owed_a = 0;
+        position.tokens_owed_b = 0;
+        position.bump = *ctx.bumps.get("position").unwrap();
+
+        Ok(())
+    }
+
+    pub fn increase_liquidity(
+        ctx: Context<IncreaseLiquidity>,
+        liquidity_delta: u128,
+        max_amount_a: u64,
+        max_amount_b: u64,
+    ) -> Result<()> {
+        require!(
+            liquidity_delta > 0 && liquidity_delta <= i128::MAX as u128,
+            ErrorCode::InvalidAmount
+        );
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.pool.paused, ErrorCode::PoolPaused);
+
+        let (amount_a, amount_b) = modify_position(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.position,
+            &ctx.accounts.tick_array_lower,
+            &ctx.accounts.tick_array_upper,
+            liquidity_delta as i128,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.reserve_a = pool.reserve_a.checked_add(amount_a).unwrap();
+        pool.reserve_b = pool.reserve_b.checked_add(amount_b).unwrap();
+
+        // The pool must receive the full amounts, so any transfer fee is added on top
+        let amount_a = amount_a
+            .checked_add(get_transfer_inverse_fee(&ctx.accounts.token_a_mint, amount_a)?)
+            .unwrap();
+        let amount_b = amount_b
+            .checked_add(get_transfer_inverse_fee(&ctx.accounts.token_b_mint, amount_b)?)
+            .unwrap();
+
+        // Check slippage
+        require!(amount_a <= max_amount_a, ErrorCode::SlippageExceeded);
+        require!(amount_b <= max_amount_b, ErrorCode::SlippageExceeded);
+
+        // Transfer tokens from user to pool
+        if amount_a > 0 {
+            transfer_from_user(
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.user_token_a.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                &ctx.accounts.token_a_mint,
+                ctx.accounts.owner.to_account_info(),
+                amount_a,
+            )?;
+        }
+
+        if amount_b > 0 {
+            transfer_from_user(
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.user_token_b.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                &ctx.accounts.token_b_mint,
+                ctx.accounts.owner.to_account_info(),
+                amount_b,
+            )?;
+        }
+
+        emit!(PositionLiquidityIncreased {
+            pool: ctx.accounts.pool.key(),
+            position: ctx.accounts.position.key(),
+            owner: ctx.accounts.owner.key(),
+            liquidity_delta,
+            amount_a,
+            amount_b,
+            reserve_a: ctx.accounts.pool.reserve_a,
+            reserve_b: ctx.accounts.pool.reserve_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn decrease_liquidity(
+        ctx: Context<DecreaseLiquidity>,
+        liquidity_delta: u128,
+        min_amount_a: u64,
+        min_amount_b: u64,
+    ) -> Result<()> {
+        require!(
+            liquidity_delta > 0 && liquidity_delta <= ctx.accounts.position.liquidity,
+            ErrorCode::InvalidAmount
+        );
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+
+        let (amount_a, amount_b) = modify_position(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.position,
+            &ctx.accounts.tick_array_lower,
+            &ctx.accounts.tick_array_upper,
+            -(liquidity_delta as i128),
+        )?;
+
+        // Check slippage against what the user receives after any transfer fees
+        let received_a = amount_a
+            .checked_sub(get_transfer_fee(&ctx.accounts.token_a_mint, amount_a)?)
+            .unwrap();
+        let received_b = amount_b
+            .checked_sub(get_transfer_fee(&ctx.accounts.token_b_mint, amount_b)?)
+            .unwrap();
+        require!(received_a >= min_amount_a, ErrorCode::SlippageExceeded);
+        require!(received_b >= min_amount_b, ErrorCode::SlippageExceeded);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.reserve_a = pool.reserve_a.checked_sub(amount_a).unwrap();
+        pool.reserve_b = pool.reserve_b.checked_sub(amount_b).unwrap();
+
+        // Transfer tokens from pool to user
+        if amount_a > 0 {
+            transfer_from_pool(
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                ctx.accounts.user_token_a.to_account_info(),
+                &ctx.accounts.token_a_mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                amount_a,
+            )?;
+        }
+
+        if amount_b > 0 {
+            transfer_from_pool(
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                ctx.accounts.user_token_b.to_account_info(),
+                &ctx.accounts.token_b_mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                amount_b,
+            )?;
+        }
+
+        emit!(PositionLiquidityDecreased {
+            pool: pool.key(),
+            position: ctx.accounts.position.key(),
+            owner: ctx.accounts.owner.key(),
+            liquidity_delta,
+            amount_a,
+            amount_b,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn collect_fees(ctx: Context<CollectFees>) -> Result<()> {
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+
+        // A zero liquidity update checkpoints the fees earned since the last update
+        modify_position(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.position,
+            &ctx.accounts.tick_array_lower,
+            &ctx.accounts.tick_array_upper,
+            0,
+        )?;
+
+        let position = &mut ctx.accounts.position;
+        let amount_a = position.tokens_owed_a;
+        let amount_b = position.tokens_owed_b;
+        position.tokens_owed_a = 0;
+        position.tokens_owed_b = 0;
+
+        // Collected fees were part of the reserves while they sat in the vaults
+        let pool = &mut ctx.accounts.pool;
+        pool.reserve_a = pool.reserve_a.checked_sub(amount_a).unwrap();
+        pool.reserve_b = pool.reserve_b.checked_sub(amount_b).unwrap();
+
+        if amount_a > 0 {
+            transfer_from_pool(
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                ctx.accounts.user_token_a.to_account_info(),
+                &ctx.accounts.token_a_mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                amount_a,
+            )?;
+        }
+
+        if amount_b > 0 {
+            transfer_from_pool(
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                ctx.accounts.user_token_b.to_account_info(),
+                &ctx.accounts.token_b_mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                amount_b,
+            )?;
+        }
+
+        emit!(PositionFeesCollected {
+            pool: pool.key(),
+            position: ctx.accounts.position.key(),
+            owner: ctx.accounts.owner.key(),
+            amount_a,
+            amount_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        let position = &ctx.accounts.position;
+        require!(
+            position.liquidity == 0 && position.tokens_owed_a == 0 && position.tokens_owed_b == 0,
+            ErrorCode::PositionNotEmpty
+        );
 
         Ok(())
     }
@@ -39,77 +612,145 @@ pub mod openfund_dex {
     ) -> Result<()> {
         // Ensure provided amounts are valid
         require!(amount_a > 0 && amount_b > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.pool.paused, ErrorCode::PoolPaused);
+
+        // Only the authority may move liquidity while a bootstrapping sale is running
+        require!(
+            !ctx.accounts.pool.sale_in_progress(Clock::get()?.unix_timestamp)
+                || ctx.accounts.user.key() == ctx.accounts.pool.authority,
+            ErrorCode::SaleInProgress
+        );
+
+        // Accumulate the pre-trade price before reserves change
+        record_price(
+            &mut ctx.accounts.pool,
+            ctx.accounts.observation_buffer.as_mut(),
+            Clock::get()?.unix_timestamp,
+        )?;
 
         let pool = &ctx.accounts.pool;
-        let token_a_supply = ctx.accounts.token_a_account.amount;
-        let token_b_supply = ctx.accounts.token_b_account.amount;
+        // Concentrated pools are funded through positions instead of the LP mint
+        require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
         let lp_supply = ctx.accounts.lp_mint.supply;
+        let balance_a = ctx.accounts.token_a_account.amount;
+        let balance_b = ctx.accounts.token_b_account.amount;
 
-        // Calculate LP tokens to mint
-        let lp_tokens = if lp_supply == 0 {
-            // Initial liquidity - Use square root of product
-            (amount_a as f64 * amount_b as f64).sqrt() as u64
-        } else {
-            // Calculate based on the ratio of existing reserves
-            let lp_amount_a = (amount_a as u128)
-                .checked_mul(lp_supply as u128)
-                .unwrap()
-                .div(token_a_supply as u128) as u64;
-            
-            let lp_amount_b = (amount_b as u128)
-                .checked_mul(lp_supply as u128)
-                .unwrap()
-                .div(token_b_supply as u128) as u64;
-            
-            // Use the minimum to prevent manipulation
-            std::cmp::min(lp_amount_a, lp_amount_b)
+        // Transfer tokens from user to pool, wrapping native SOL into a temporary account
+        let user_source_a = match &ctx.accounts.user_token_a {
+            Some(user_token_a) => user_token_a.to_account_info(),
+            None => wrap_sol(
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.system_program.as_ref(),
+                ctx.accounts.wsol_account.as_ref(),
+                ctx.bumps.get("wsol_account"),
+                &ctx.accounts.token_a_mint,
+                ctx.accounts.user.to_account_info(),
+                amount_a,
+            )?,
         };
-
-        // Ensure the minimum LP tokens requirement is met
-        require!(lp_tokens >= min_lp_tokens, ErrorCode::SlippageExceeded);
-
-        // Transfer tokens from user to pool
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.user_token_a.to_account_info(),
-                    to: ctx.accounts.token_a_account.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                },
-            ),
+        transfer_from_user(
+            ctx.accounts.token_a_program.to_account_info(),
+            user_source_a.clone(),
+            ctx.accounts.token_a_account.to_account_info(),
+            &ctx.accounts.token_a_mint,
+            ctx.accounts.user.to_account_info(),
             amount_a,
         )?;
+        if ctx.accounts.user_token_a.is_none() {
+            unwrap_sol(
+                ctx.accounts.token_a_program.to_account_info(),
+                user_source_a,
+                ctx.accounts.user.to_account_info(),
+            )?;
+        }
 
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.user_token_b.to_account_info(),
-                    to: ctx.accounts.token_b_account.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                },
-            ),
+        let user_source_b = match &ctx.accounts.user_token_b {
+            Some(user_token_b) => user_token_b.to_account_info(),
+            None => wrap_sol(
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.system_program.as_ref(),
+                ctx.accounts.wsol_account.as_ref(),
+                ctx.bumps.get("wsol_account"),
+                &ctx.accounts.token_b_mint,
+                ctx.accounts.user.to_account_info(),
+                amount_b,
+            )?,
+        };
+        transfer_from_user(
+            ctx.accounts.token_b_program.to_account_info(),
+            user_source_b.clone(),
+            ctx.accounts.token_b_account.to_account_info(),
+            &ctx.accounts.token_b_mint,
+            ctx.accounts.user.to_account_info(),
             amount_b,
         )?;
+        if ctx.accounts.user_token_b.is_none() {
+            unwrap_sol(
+                ctx.accounts.token_b_program.to_account_info(),
+                user_source_b,
+                ctx.accounts.user.to_account_info(),
+            )?;
+        }
+
+        // Transfer fees mean the pool can receive less than was sent, so LP tokens
+        // are priced on what actually arrived
+        ctx.accounts.token_a_account.reload()?;
+        ctx.accounts.token_b_account.reload()?;
+        let amount_a = ctx.accounts.token_a_account.amount.checked_sub(balance_a).unwrap();
+        let amount_b = ctx.accounts.token_b_account.amount.checked_sub(balance_b).unwrap();
+        require!(amount_a > 0 && amount_b > 0, ErrorCode::InvalidAmount);
+
+        // Calculate LP tokens to mint and the reserves they leave behind
+        let deposit =
+            apply_deposit(pool, amount_a, amount_b, lp_supply, Clock::get()?.unix_timestamp)?;
+        if deposit.locked_lp > 0 {
+            token_interface::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::MintTo {
+                        mint: ctx.accounts.lp_mint.to_account_info(),
+                        to: ctx.accounts.locked_lp_token.to_account_info(),
+                        authority: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                    &[&[b"pool_authority", pool.key().as_ref(), &[pool.authority_bump]][..]],
+                ),
+                deposit.locked_lp,
+            )?;
+        }
+        let lp_tokens = deposit.lp_tokens;
+
+        // Ensure the minimum LP tokens requirement is met
+        require!(lp_tokens >= min_lp_tokens, ErrorCode::SlippageExceeded);
 
         // Mint LP tokens to user
-        token::mint_to(
+        token_interface::mint_to(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                token::MintTo {
+                token_interface::MintTo {
                     mint: ctx.accounts.lp_mint.to_account_info(),
                     to: ctx.accounts.user_lp_token.to_account_info(),
                     authority: ctx.accounts.pool_authority.to_account_info(),
                 },
-                &[&[
-                    b"pool_authority", 
-                    pool.bump.to_le_bytes().as_ref()
-                ][..]],
+                &[&[b"pool_authority", pool.key().as_ref(), &[pool.authority_bump]][..]],
             ),
             lp_tokens,
         )?;
 
+        let pool = &mut ctx.accounts.pool;
+        pool.reserve_a = deposit.reserve_a;
+        pool.reserve_b = deposit.reserve_b;
+
+        emit!(LiquidityAdded {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amount_a,
+            amount_b,
+            lp_tokens,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+        });
+
         Ok(())
     }
 
@@ -120,13 +761,31 @@ pub mod openfund_dex {
         min_amount_b: u64,
     ) -> Result<()> {
         require!(lp_amount > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
         
+        // Only the authority may move liquidity while a bootstrapping sale is running
+        require!(
+            !ctx.accounts.pool.sale_in_progress(Clock::get()?.unix_timestamp)
+                || ctx.accounts.user.key() == ctx.accounts.pool.authority,
+            ErrorCode::SaleInProgress
+        );
+
+        // Accumulate the pre-trade price before reserves change
+        record_price(
+            &mut ctx.accounts.pool,
+            ctx.accounts.observation_buffer.as_mut(),
+            Clock::get()?.unix_timestamp,
+        )?;
+
         let pool = &ctx.accounts.pool;
-        let token_a_supply = ctx.accounts.token_a_account.amount;
-        let token_b_supply = ctx.accounts.token_b_account.amount;
+        require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
+        let token_a_supply = pool.reserve_a;
+        let token_b_supply = pool.reserve_b;
         let lp_supply = ctx.accounts.lp_mint.supply;
         
         // Calculate token amounts to return
+        // Proportional withdrawals leave the price unchanged under every invariant,
+        // so constant-product and stable pools share this path
         let amount_a = (lp_amount as u128)
             .checked_mul(token_a_supply as u128)
             .unwrap()
@@ -137,15 +796,21 @@ pub mod openfund_dex {
             .unwrap()
             .div(lp_supply as u128) as u64;
             
-        // Check slippage
-        require!(amount_a >= min_amount_a, ErrorCode::SlippageExceeded);
-        require!(amount_b >= min_amount_b, ErrorCode::SlippageExceeded);
+        // Check slippage against what the user receives after any transfer fees
+        let received_a = amount_a
+            .checked_sub(get_transfer_fee(&ctx.accounts.token_a_mint, amount_a)?)
+            .unwrap();
+        let received_b = amount_b
+            .checked_sub(get_transfer_fee(&ctx.accounts.token_b_mint, amount_b)?)
+            .unwrap();
+        require!(received_a >= min_amount_a, ErrorCode::SlippageExceeded);
+        require!(received_b >= min_amount_b, ErrorCode::SlippageExceeded);
         
         // Burn LP tokens
-        token::burn(
+        token_interface::burn(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                token::Burn {
+                token_interface::Burn {
                     mint: ctx.accounts.lp_mint.to_account_info(),
                     from: ctx.accounts.user_lp_token.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
@@ -155,375 +820,7676 @@ pub mod openfund_dex {
         )?;
         
         // Transfer tokens from pool to user
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.token_a_account.to_account_info(),
-                    to: ctx.accounts.user_token_a.to_account_info(),
-                    authority: ctx.accounts.pool_authority.to_account_info(),
-                },
-                &[&[
-                    b"pool_authority", 
-                    pool.bump.to_le_bytes().as_ref()
-                ][..]],
-            ),
+        transfer_from_pool(
+            ctx.accounts.token_a_program.to_account_info(),
+            ctx.accounts.token_a_account.to_account_info(),
+            ctx.accounts.user_token_a.to_account_info(),
+            &ctx.accounts.token_a_mint,
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
             amount_a,
         )?;
         
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.token_b_account.to_account_info(),
-                    to: ctx.accounts.user_token_b.to_account_info(),
-                    authority: ctx.accounts.pool_authority.to_account_info(),
-                },
-                &[&[
-                    b"pool_authority", 
-                    pool.bump.to_le_bytes().as_ref()
-                ][..]],
-            ),
+        transfer_from_pool(
+            ctx.accounts.token_b_program.to_account_info(),
+            ctx.accounts.token_b_account.to_account_info(),
+            ctx.accounts.user_token_b.to_account_info(),
+            &ctx.accounts.token_b_mint,
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
             amount_b,
         )?;
         
+        let pool = &mut ctx.accounts.pool;
+        pool.reserve_a = token_a_supply.checked_sub(amount_a).unwrap();
+        pool.reserve_b = token_b_supply.checked_sub(amount_b).unwrap();
+
+        emit!(LiquidityRemoved {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amount_a,
+            amount_b,
+            lp_tokens: lp_amount,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+        });
+
         Ok(())
     }
 
-    pub fn swap(
-        ctx: Context<Swap>,
+    pub fn add_liquidity_single_sided(
+        ctx: Context<AddLiquiditySingleSided>,
         amount_in: u64,
-        min_amount_out: u64,
+        min_lp_tokens: u64,
     ) -> Result<()> {
         require!(amount_in > 0, ErrorCode::InvalidAmount);
-        
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.pool.paused, ErrorCode::PoolPaused);
+
+        // Only the authority may move liquidity while a bootstrapping sale is running
+        require!(
+            !ctx.accounts.pool.sale_in_progress(Clock::get()?.unix_timestamp)
+                || ctx.accounts.user.key() == ctx.accounts.pool.authority,
+            ErrorCode::SaleInProgress
+        );
+
+        // Accumulate the pre-trade price before reserves change
+        record_price(
+            &mut ctx.accounts.pool,
+            ctx.accounts.observation_buffer.as_mut(),
+            Clock::get()?.unix_timestamp,
+        )?;
+
         let pool = &ctx.accounts.pool;
-        
-        // Calculate the fee
-        let fee = (amount_in as u128)
-            .checked_mul(pool.fee_numerator as u128)
-            .unwrap()
-            .div(pool.fee_denominator as u128) as u64;
-            
-        // Calculate the amount in after fee
-        let amount_in_after_fee = amount_in.checked_sub(fee).unwrap();
-        
-        // Determine which token is being swapped in/out
-        let (reserve_in, reserve_out) = if ctx.accounts.user_token_in.mint == pool.token_a_mint {
-            (ctx.accounts.token_a_account.amount, ctx.accounts.token_b_account.amount)
+        let token_a_supply = pool.reserve_a;
+        let token_b_supply = pool.reserve_b;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+
+        // The pool must already be priced by a two-sided deposit
+        require!(lp_supply > 0, ErrorCode::EmptyPool);
+
+        let a_to_b = ctx.accounts.user_token_in.mint == pool.token_a_mint;
+        let clock = Clock::get()?;
+        let price_before_x64 = spot_prices_x64(
+            pool,
+            token_a_supply,
+            token_b_supply,
+            clock.unix_timestamp,
+        )
+        .map(|(price_a_x64, _)| price_a_x64);
+
+        // Transfer the whole deposit to the pool, the internal swap leaves it in place
+        let (token_program_in, vault_in, mint_in) = if a_to_b {
+            (
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                &ctx.accounts.token_a_mint,
+            )
         } else {
-            (ctx.accounts.token_b_account.amount, ctx.accounts.token_a_account.amount)
+            (
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                &ctx.accounts.token_b_mint,
+            )
         };
-        
-        // Calculate amount out using constant product formula: (x * y = k)
-        // new_reserve_out = (reserve_in * reserve_out) / (reserve_in + amount_in_after_fee)
-        // amount_out = reserve_out - new_reserve_out
-        
-        let new_reserve_in = reserve_in.checked_add(amount_in_after_fee).unwrap();
-        let product = (reserve_in as u128).checked_mul(reserve_out as u128).unwrap();
-        let new_reserve_out = product.div(new_reserve_in as u128) as u64;
-        let amount_out = reserve_out.checked_sub(new_reserve_out).unwrap();
-        
-        // Check slippage
-        require!(amount_out >= min_amount_out, ErrorCode::SlippageExceeded);
-        
-        // Transfer token in from user to pool
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.user_token_in.to_account_info(),
-                    to: if ctx.accounts.user_token_in.mint == pool.token_a_mint {
-                        ctx.accounts.token_a_account.to_account_info()
-                    } else {
-                        ctx.accounts.token_b_account.to_account_info()
-                    },
-                    authority: ctx.accounts.user.to_account_info(),
-                },
-            ),
+        transfer_from_user(
+            token_program_in,
+            ctx.accounts.user_token_in.to_account_info(),
+            vault_in,
+            mint_in,
+            ctx.accounts.user.to_account_info(),
             amount_in,
         )?;
-        
-        // Transfer token out from pool to user
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: if ctx.accounts.user_token_out.mint == pool.token_a_mint {
-                        ctx.accounts.token_a_account.to_account_info()
-                    } else {
-                        ctx.accounts.token_b_account.to_account_info()
-                    },
-                    to: ctx.accounts.user_token_out.to_account_info(),
+
+        // Transfer fees mean the pool can receive less than was sent
+        let amount_in = if a_to_b {
+            let balance_before = ctx.accounts.token_a_account.amount;
+            ctx.accounts.token_a_account.reload()?;
+            ctx.accounts.token_a_account.amount.checked_sub(balance_before).unwrap()
+        } else {
+            let balance_before = ctx.accounts.token_b_account.amount;
+            ctx.accounts.token_b_account.reload()?;
+            ctx.accounts.token_b_account.amount.checked_sub(balance_before).unwrap()
+        };
+
+        // Calculate LP tokens to mint for the one-token deposit
+        let lp_tokens = match pool.pool_type {
+            PoolType::ConstantProduct => {
+                let (reserve_in, reserve_out) = if a_to_b {
+                    (token_a_supply, token_b_supply)
+                } else {
+                    (token_b_supply, token_a_supply)
+                };
+                calculate_single_sided_lp_tokens(
+                    pool,
+                    pool.swap_fee_numerator(clock.unix_timestamp),
+                    a_to_b,
+                    reserve_in,
+                    reserve_out,
+                    amount_in,
+                    lp_supply,
+                    clock.unix_timestamp,
+                )
+            }
+            PoolType::Weighted => {
+                let (weight_in, _) = pool.swap_weights(a_to_b, clock.unix_timestamp);
+                let reserve_in = if a_to_b { token_a_supply } else { token_b_supply };
+                calculate_weighted_single_sided_lp_tokens(
+                    pool.swap_fee_numerator(clock.unix_timestamp),
+                    pool.fee_denominator,
+                    reserve_in,
+                    weight_in,
+                    amount_in,
+                    lp_supply,
+                )
+            }
+            PoolType::StableSwap => {
+                // The stable deposit math already charges the imbalance fee
+                let (amount_a, amount_b) = if a_to_b { (amount_in, 0) } else { (0, amount_in) };
+                calculate_stable_lp_tokens(
+                    pool,
+                    pool.swap_fee_numerator(clock.unix_timestamp),
+                    pool.current_amp(clock.unix_timestamp),
+                    token_a_supply,
+                    token_b_supply,
+                    amount_a,
+                    amount_b,
+                    lp_supply,
+                )
+            }
+            // PMM prices move with the oracle, which deposits do not read
+            PoolType::Concentrated | PoolType::Pmm => return err!(ErrorCode::InvalidPoolType),
+        }
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        // Ensure the minimum LP tokens requirement is met
+        require!(lp_tokens > 0, ErrorCode::InvalidAmount);
+        require!(lp_tokens >= min_lp_tokens, ErrorCode::SlippageExceeded);
+
+        // Mint LP tokens to user
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.user_lp_token.to_account_info(),
                     authority: ctx.accounts.pool_authority.to_account_info(),
                 },
-                &[&[
-                    b"pool_authority", 
-                    pool.bump.to_le_bytes().as_ref()
-                ][..]],
+                &[&[b"pool_authority", pool.key().as_ref(), &[pool.authority_bump]][..]],
+            ),
+            lp_tokens,
+        )?;
+
+        let (amount_a, amount_b) = if a_to_b { (amount_in, 0) } else { (0, amount_in) };
+        let reserve_a = token_a_supply.checked_add(amount_a).unwrap();
+        let reserve_b = token_b_supply.checked_add(amount_b).unwrap();
+
+        // The deposit moves the price like the swap it stands in for
+        let price_after_x64 = spot_prices_x64(
+            &ctx.accounts.pool,
+            reserve_a,
+            reserve_b,
+            clock.unix_timestamp,
+        )
+        .map(|(price_a_x64, _)| price_a_x64);
+        check_oracle_guard(
+            &ctx.accounts.pool,
+            ctx.accounts.oracle_config.as_ref(),
+            ctx.accounts.price_feed.as_ref(),
+            (ctx.accounts.token_a_mint.decimals, ctx.accounts.token_b_mint.decimals),
+            price_before_x64,
+            price_after_x64,
+            clock.unix_timestamp,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.check_circuit_breaker(price_before_x64, price_after_x64, clock.slot)?;
+        pool.record_volatility((reserve_a, reserve_b), clock.unix_timestamp)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.reserve_a = reserve_a;
+        pool.reserve_b = reserve_b;
+
+        emit!(LiquidityAdded {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amount_a,
+            amount_b,
+            lp_tokens,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_liquidity_single_sided(
+        ctx: Context<RemoveLiquiditySingleSided>,
+        lp_amount: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        require!(lp_amount > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+
+        // Only the authority may move liquidity while a bootstrapping sale is running
+        require!(
+            !ctx.accounts.pool.sale_in_progress(Clock::get()?.unix_timestamp)
+                || ctx.accounts.user.key() == ctx.accounts.pool.authority,
+            ErrorCode::SaleInProgress
+        );
+
+        // Accumulate the pre-trade price before reserves change
+        record_price(
+            &mut ctx.accounts.pool,
+            ctx.accounts.observation_buffer.as_mut(),
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        let pool = &ctx.accounts.pool;
+        require!(
+            pool.pool_type != PoolType::Concentrated && pool.pool_type != PoolType::Pmm,
+            ErrorCode::InvalidPoolType
+        );
+
+        let out_is_a = ctx.accounts.user_token_out.mint == pool.token_a_mint;
+        let (reserve_out, reserve_other) = if out_is_a {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        let clock = Clock::get()?;
+        let price_before_x64 = spot_prices_x64(
+            pool,
+            pool.reserve_a,
+            pool.reserve_b,
+            clock.unix_timestamp,
+        )
+        .map(|(price_a_x64, _)| price_a_x64);
+
+        // Withdraw proportionally first
+        let share_out = (lp_amount as u128)
+            .checked_mul(reserve_out as u128)
+            .unwrap()
+            .div(lp_supply as u128) as u64;
+        let share_other = (lp_amount as u128)
+            .checked_mul(reserve_other as u128)
+            .unwrap()
+            .div(lp_supply as u128) as u64;
+
+        // Then swap the other side into the requested token against the remaining reserves
+        let fee = (share_other as u128)
+            .checked_mul(pool.swap_fee_numerator(clock.unix_timestamp) as u128)
+            .unwrap()
+            .div(pool.fee_denominator as u128) as u64;
+        let swapped_out = calculate_swap_output(
+            pool,
+            !out_is_a,
+            reserve_other.checked_sub(share_other).unwrap(),
+            reserve_out.checked_sub(share_out).unwrap(),
+            share_other.checked_sub(fee).unwrap(),
+            clock.unix_timestamp,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        let amount_out = share_out.checked_add(swapped_out).unwrap();
+
+        let (token_program_out, vault_out, mint_out) = if out_is_a {
+            (
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                &ctx.accounts.token_a_mint,
+            )
+        } else {
+            (
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                &ctx.accounts.token_b_mint,
+            )
+        };
+
+        // Check slippage against what the user receives after any transfer fee
+        let amount_received = amount_out
+            .checked_sub(get_transfer_fee(mint_out, amount_out)?)
+            .unwrap();
+        require!(amount_received >= min_amount_out, ErrorCode::SlippageExceeded);
+
+        // Burn LP tokens
+        token_interface::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_token.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
             ),
+            lp_amount,
+        )?;
+
+        // Transfer tokens from pool to user
+        transfer_from_pool(
+            token_program_out,
+            vault_out,
+            ctx.accounts.user_token_out.to_account_info(),
+            mint_out,
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
+            amount_out,
+        )?;
+
+        let reserve_out = reserve_out.checked_sub(amount_out).unwrap();
+        let (amount_a, amount_b, reserve_a, reserve_b) = if out_is_a {
+            (amount_out, 0, reserve_out, reserve_other)
+        } else {
+            (0, amount_out, reserve_other, reserve_out)
+        };
+
+        // The withdrawal moves the price like the swap it stands in for
+        let price_after_x64 = spot_prices_x64(
+            &ctx.accounts.pool,
+            reserve_a,
+            reserve_b,
+            clock.unix_timestamp,
+        )
+        .map(|(price_a_x64, _)| price_a_x64);
+        check_oracle_guard(
+            &ctx.accounts.pool,
+            ctx.accounts.oracle_config.as_ref(),
+            ctx.accounts.price_feed.as_ref(),
+            (ctx.accounts.token_a_mint.decimals, ctx.accounts.token_b_mint.decimals),
+            price_before_x64,
+            price_after_x64,
+            clock.unix_timestamp,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.check_circuit_breaker(price_before_x64, price_after_x64, clock.slot)?;
+        pool.record_volatility((reserve_a, reserve_b), clock.unix_timestamp)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.reserve_a = reserve_a;
+        pool.reserve_b = reserve_b;
+
+        emit!(LiquidityRemoved {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amount_a,
+            amount_b,
+            lp_tokens: lp_amount,
+            reserve_a,
+            reserve_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        require!(amount_in > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.pool.paused, ErrorCode::PoolPaused);
+        
+        // Accumulate the pre-trade price before reserves change
+        record_price(
+            &mut ctx.accounts.pool,
+            ctx.accounts.observation_buffer.as_mut(),
+            Clock::get()?.unix_timestamp,
+        )?;
+        refresh_pmm_price(
+            &mut ctx.accounts.pool,
+            ctx.accounts.oracle_config.as_ref(),
+            ctx.accounts.price_feed.as_ref(),
+            (ctx.accounts.token_a_mint.decimals, ctx.accounts.token_b_mint.decimals),
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        let (mint_in_key, mint_out_key) = swap_mints(
+            &ctx.accounts.pool,
+            ctx.accounts.user_token_in.as_ref(),
+            ctx.accounts.user_token_out.as_ref(),
+        )?;
+        let a_to_b = mint_in_key == ctx.accounts.pool.token_a_mint;
+        let price_before_x64 = spot_prices_x64(
+            &ctx.accounts.pool,
+            ctx.accounts.pool.reserve_a,
+            ctx.accounts.pool.reserve_b,
+            Clock::get()?.unix_timestamp,
+        )
+        .map(|(price_a_x64, _)| price_a_x64);
+        
+        // Determine which token is being swapped in/out
+        let (reserve_in, reserve_out) = if a_to_b {
+            (ctx.accounts.pool.reserve_a, ctx.accounts.pool.reserve_b)
+        } else {
+            (ctx.accounts.pool.reserve_b, ctx.accounts.pool.reserve_a)
+        };
+
+        // Transfer token in from user to pool
+        let (token_program_in, vault_in, mint_in) = if a_to_b {
+            (
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                &ctx.accounts.token_a_mint,
+            )
+        } else {
+            (
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                &ctx.accounts.token_b_mint,
+            )
+        };
+
+        // Native SOL is wrapped into a temporary account that is closed once the pool is paid
+        let user_source = match &ctx.accounts.user_token_in {
+            Some(user_token_in) => user_token_in.to_account_info(),
+            None => wrap_sol(
+                token_program_in.clone(),
+                ctx.accounts.system_program.as_ref(),
+                ctx.accounts.wsol_account.as_ref(),
+                ctx.bumps.get("wsol_account"),
+                mint_in,
+                ctx.accounts.user.to_account_info(),
+                amount_in,
+            )?,
+        };
+        transfer_from_user(
+            token_program_in.clone(),
+            user_source.clone(),
+            vault_in.clone(),
+            mint_in,
+            ctx.accounts.user.to_account_info(),
+            amount_in,
+        )?;
+        if ctx.accounts.user_token_in.is_none() {
+            unwrap_sol(token_program_in.clone(), user_source, ctx.accounts.user.to_account_info())?;
+        }
+
+        // Transfer fees mean the pool can receive less than was sent,
+        // so the output is computed from what actually arrived
+        let amount_received = if a_to_b {
+            let balance_before = ctx.accounts.token_a_account.amount;
+            ctx.accounts.token_a_account.reload()?;
+            ctx.accounts.token_a_account.amount.checked_sub(balance_before).unwrap()
+        } else {
+            let balance_before = ctx.accounts.token_b_account.amount;
+            ctx.accounts.token_b_account.reload()?;
+            ctx.accounts.token_b_account.amount.checked_sub(balance_before).unwrap()
+        };
+        require!(amount_received > 0, ErrorCode::InvalidAmount);
+
+        let (amount_out, fee) = if ctx.accounts.pool.pool_type == PoolType::Concentrated {
+            // Concentrated pools charge the fee step by step while walking the ticks,
+            // with the tick arrays to cross passed in swap direction
+            let pool_key = ctx.accounts.pool.key();
+            swap_concentrated(
+                &mut ctx.accounts.pool,
+                pool_key,
+                ctx.remaining_accounts,
+                amount_received,
+                a_to_b,
+            )?
+        } else {
+            let pool = &ctx.accounts.pool;
+            let clock = Clock::get()?;
+
+            // Calculate the fee
+            let fee = (amount_received as u128)
+                .checked_mul(pool.swap_fee_numerator(clock.unix_timestamp) as u128)
+                .unwrap()
+                .div(pool.fee_denominator as u128) as u64;
+
+            // Calculate the amount in after fee
+            let amount_in_after_fee = amount_received.checked_sub(fee).unwrap();
+
+            // Calculate amount out using the pool's invariant
+            let amount_out = calculate_swap_output(
+                pool,
+                a_to_b,
+                reserve_in,
+                reserve_out,
+                amount_in_after_fee,
+                clock.unix_timestamp,
+            )
+            .ok_or(ErrorCode::MathOverflow)?;
+            (amount_out, fee)
+        };
+        
+        let pool = &ctx.accounts.pool;
+        let (token_program_out, vault_out, mint_out) = if a_to_b {
+            (
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                &ctx.accounts.token_b_mint,
+            )
+        } else {
+            (
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                &ctx.accounts.token_a_mint,
+            )
+        };
+
+        // Check slippage against what the user receives after any transfer fee
+        let amount_out_received = amount_out
+            .checked_sub(get_transfer_fee(mint_out, amount_out)?)
+            .unwrap();
+        require!(amount_out_received >= min_amount_out, ErrorCode::SlippageExceeded);
+        
+        // Transfer token out from pool to user, unwrapping native SOL back to lamports
+        let user_destination = match &ctx.accounts.user_token_out {
+            Some(user_token_out) => user_token_out.to_account_info(),
+            None => wrap_sol(
+                token_program_out.clone(),
+                ctx.accounts.system_program.as_ref(),
+                ctx.accounts.wsol_account.as_ref(),
+                ctx.bumps.get("wsol_account"),
+                mint_out,
+                ctx.accounts.user.to_account_info(),
+                0,
+            )?,
+        };
+        transfer_from_pool(
+            token_program_out.clone(),
+            vault_out,
+            user_destination.clone(),
+            mint_out,
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
             amount_out,
         )?;
+        if ctx.accounts.user_token_out.is_none() {
+            unwrap_sol(token_program_out, user_destination, ctx.accounts.user.to_account_info())?;
+        }
+        // Referrers earn their share of the swap fee, paid straight out of the input vault
+        let referral_fee = pay_referral(
+            pool,
+            token_program_in,
+            vault_in,
+            mint_in,
+            ctx.accounts.pool_authority.to_account_info(),
+            ctx.accounts.user.key(),
+            ctx.accounts.referrer_token.as_ref(),
+            ctx.accounts.referrer_stats.as_mut(),
+            ctx.accounts.fee_config.as_ref(),
+            amount_received,
+            fee,
+        )?;
         
+        let reserve_in = reserve_in
+            .checked_add(amount_received.checked_sub(referral_fee).unwrap())
+            .unwrap();
+        let reserve_out = reserve_out.checked_sub(amount_out).unwrap();
+        let (reserve_a, reserve_b) = if a_to_b {
+            (reserve_in, reserve_out)
+        } else {
+            (reserve_out, reserve_in)
+        };
+
+        // Guarded pools may not be pushed away from their oracle's price
+        let now = Clock::get()?.unix_timestamp;
+        let price_after_x64 = spot_prices_x64(&ctx.accounts.pool, reserve_a, reserve_b, now)
+            .map(|(price_a_x64, _)| price_a_x64);
+        check_oracle_guard(
+            &ctx.accounts.pool,
+            ctx.accounts.oracle_config.as_ref(),
+            ctx.accounts.price_feed.as_ref(),
+            (ctx.accounts.token_a_mint.decimals, ctx.accounts.token_b_mint.decimals),
+            price_before_x64,
+            price_after_x64,
+            now,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.check_circuit_breaker(price_before_x64, price_after_x64, Clock::get()?.slot)?;
+        pool.record_volatility((reserve_a, reserve_b), now)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.reserve_a = reserve_a;
+        pool.reserve_b = reserve_b;
+
+        emit!(SwapExecuted {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            mint_in: mint_in_key,
+            mint_out: mint_out_key,
+            amount_in: amount_received,
+            amount_out,
+            fee,
+            reserve_a,
+            reserve_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn swap_exact_out(
+        ctx: Context<Swap>,
+        amount_out: u64,
+        max_amount_in: u64,
+    ) -> Result<()> {
+        require!(amount_out > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.pool.paused, ErrorCode::PoolPaused);
+
+        // Accumulate the pre-trade price before reserves change
+        record_price(
+            &mut ctx.accounts.pool,
+            ctx.accounts.observation_buffer.as_mut(),
+            Clock::get()?.unix_timestamp,
+        )?;
+        refresh_pmm_price(
+            &mut ctx.accounts.pool,
+            ctx.accounts.oracle_config.as_ref(),
+            ctx.accounts.price_feed.as_ref(),
+            (ctx.accounts.token_a_mint.decimals, ctx.accounts.token_b_mint.decimals),
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        let pool = &ctx.accounts.pool;
+        // Concentrated pools only quote exact-input swaps
+        require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
+
+        // Determine which token is being swapped in/out
+        let (mint_in_key, mint_out_key) = swap_mints(
+            pool,
+            ctx.accounts.user_token_in.as_ref(),
+            ctx.accounts.user_token_out.as_ref(),
+        )?;
+        let a_to_b = mint_in_key == pool.token_a_mint;
+        let price_before_x64 =
+            spot_prices_x64(pool, pool.reserve_a, pool.reserve_b, Clock::get()?.unix_timestamp)
+                .map(|(price_a_x64, _)| price_a_x64);
+        let (reserve_in, reserve_out) = if a_to_b {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
+        let (token_program_in, vault_in, mint_in, token_program_out, vault_out, mint_out) =
+            if a_to_b {
+                (
+                    ctx.accounts.token_a_program.to_account_info(),
+                    ctx.accounts.token_a_account.to_account_info(),
+                    &ctx.accounts.token_a_mint,
+                    ctx.accounts.token_b_program.to_account_info(),
+                    ctx.accounts.token_b_account.to_account_info(),
+                    &ctx.accounts.token_b_mint,
+                )
+            } else {
+                (
+                    ctx.accounts.token_b_program.to_account_info(),
+                    ctx.accounts.token_b_account.to_account_info(),
+                    &ctx.accounts.token_b_mint,
+                    ctx.accounts.token_a_program.to_account_info(),
+                    ctx.accounts.token_a_account.to_account_info(),
+                    &ctx.accounts.token_a_mint,
+                )
+            };
+
+        // The pool sends enough for the user to receive `amount_out` after any transfer fee
+        let amount_sent = amount_out
+            .checked_add(get_transfer_inverse_fee(mint_out, amount_out)?)
+            .unwrap();
+        require!(amount_sent < reserve_out, ErrorCode::InsufficientLiquidity);
+
+        // Invert the pool's invariant for the net input, then gross it up for the fee
+        let clock = Clock::get()?;
+        let amount_in_after_fee = calculate_swap_input(
+            pool,
+            a_to_b,
+            reserve_in,
+            reserve_out,
+            amount_sent,
+            clock.unix_timestamp,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        let amount_received = calculate_amount_before_fee(
+            pool,
+            pool.swap_fee_numerator(clock.unix_timestamp),
+            amount_in_after_fee,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        let fee = amount_received.checked_sub(amount_in_after_fee).unwrap();
+
+        // The user sends enough for the pool to receive the input after any transfer fee
+        let amount_in = amount_received
+            .checked_add(get_transfer_inverse_fee(mint_in, amount_received)?)
+            .unwrap();
+
+        // Check slippage
+        require!(amount_in <= max_amount_in, ErrorCode::SlippageExceeded);
+
+        // Transfer token in from user to pool, wrapping native SOL into a temporary account
+        let user_source = match &ctx.accounts.user_token_in {
+            Some(user_token_in) => user_token_in.to_account_info(),
+            None => wrap_sol(
+                token_program_in.clone(),
+                ctx.accounts.system_program.as_ref(),
+                ctx.accounts.wsol_account.as_ref(),
+                ctx.bumps.get("wsol_account"),
+                mint_in,
+                ctx.accounts.user.to_account_info(),
+                amount_in,
+            )?,
+        };
+        transfer_from_user(
+            token_program_in.clone(),
+            user_source.clone(),
+            vault_in.clone(),
+            mint_in,
+            ctx.accounts.user.to_account_info(),
+            amount_in,
+        )?;
+        if ctx.accounts.user_token_in.is_none() {
+            unwrap_sol(token_program_in.clone(), user_source, ctx.accounts.user.to_account_info())?;
+        }
+
+        // Transfer token out from pool to user, unwrapping native SOL back to lamports
+        let user_destination = match &ctx.accounts.user_token_out {
+            Some(user_token_out) => user_token_out.to_account_info(),
+            None => wrap_sol(
+                token_program_out.clone(),
+                ctx.accounts.system_program.as_ref(),
+                ctx.accounts.wsol_account.as_ref(),
+                ctx.bumps.get("wsol_account"),
+                mint_out,
+                ctx.accounts.user.to_account_info(),
+                0,
+            )?,
+        };
+        transfer_from_pool(
+            token_program_out.clone(),
+            vault_out,
+            user_destination.clone(),
+            mint_out,
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
+            amount_sent,
+        )?;
+        if ctx.accounts.user_token_out.is_none() {
+            unwrap_sol(token_program_out, user_destination, ctx.accounts.user.to_account_info())?;
+        }
+        // Referrers earn their share of the swap fee, paid straight out of the input vault
+        let referral_fee = pay_referral(
+            pool,
+            token_program_in,
+            vault_in,
+            mint_in,
+            ctx.accounts.pool_authority.to_account_info(),
+            ctx.accounts.user.key(),
+            ctx.accounts.referrer_token.as_ref(),
+            ctx.accounts.referrer_stats.as_mut(),
+            ctx.accounts.fee_config.as_ref(),
+            amount_received,
+            fee,
+        )?;
+
+        let reserve_in = reserve_in
+            .checked_add(amount_received.checked_sub(referral_fee).unwrap())
+            .unwrap();
+        let reserve_out = reserve_out.checked_sub(amount_sent).unwrap();
+        let (reserve_a, reserve_b) = if a_to_b {
+            (reserve_in, reserve_out)
+        } else {
+            (reserve_out, reserve_in)
+        };
+
+        // Guarded pools may not be pushed away from their oracle's price
+        let now = Clock::get()?.unix_timestamp;
+        let price_after_x64 = spot_prices_x64(&ctx.accounts.pool, reserve_a, reserve_b, now)
+            .map(|(price_a_x64, _)| price_a_x64);
+        check_oracle_guard(
+            &ctx.accounts.pool,
+            ctx.accounts.oracle_config.as_ref(),
+            ctx.accounts.price_feed.as_ref(),
+            (ctx.accounts.token_a_mint.decimals, ctx.accounts.token_b_mint.decimals),
+            price_before_x64,
+            price_after_x64,
+            now,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.check_circuit_breaker(price_before_x64, price_after_x64, Clock::get()?.slot)?;
+        pool.record_volatility((reserve_a, reserve_b), now)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.reserve_a = reserve_a;
+        pool.reserve_b = reserve_b;
+
+        emit!(SwapExecuted {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            mint_in: mint_in_key,
+            mint_out: mint_out_key,
+            amount_in: amount_received,
+            amount_out: amount_sent,
+            fee,
+            reserve_a,
+            reserve_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn route_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        require!(amount_in > 0, ErrorCode::InvalidAmount);
+
+        // Each hop passes [pool, pool_authority, token_a_account, token_b_account, token_a_mint,
+        // token_b_mint, observation_buffer] in route order. Pools and buffers are writable so
+        // their price accumulators can update, a pool without a buffer passes the program id
+        let hops = ctx.remaining_accounts;
+        require!(
+            !hops.is_empty()
+                && hops.len() % ROUTE_ACCOUNTS_PER_HOP == 0
+                && hops.len() / ROUTE_ACCOUNTS_PER_HOP <= MAX_ROUTE_HOPS,
+            ErrorCode::InvalidRoute
+        );
+
+        // Hops may mix SPL Token and Token-2022 mints, each moved by the program that owns it
+        let token_program_for = |mint: &InterfaceAccount<'info, Mint>| {
+            if *mint.to_account_info().owner == Token::id() {
+                ctx.accounts.token_program.to_account_info()
+            } else {
+                ctx.accounts.token_2022_program.to_account_info()
+            }
+        };
+
+        let clock = Clock::get()?;
+        let mut visited_pools: Vec<Pubkey> = Vec::with_capacity(MAX_ROUTE_HOPS);
+        let mut mint_in = ctx.accounts.user_token_in.mint;
+        let mut amount = amount_in;
+        // Pool vault holding the previous hop's output, with the authority that can move it,
+        // the pool and authority bump it signs with and the mint of the token it holds
+        let mut previous_hop: Option<(
+            AccountInfo<'info>,
+            AccountInfo<'info>,
+            Pubkey,
+            u8,
+            InterfaceAccount<'info, Mint>,
+        )> = None;
+
+        for hop in hops.chunks(ROUTE_ACCOUNTS_PER_HOP) {
+            let mut pool = Account::<Pool>::try_from(&hop[0])?;
+            let pool_authority = &hop[1];
+            let mut token_a_account = InterfaceAccount::<TokenAccount>::try_from(&hop[2])?;
+            let mut token_b_account = InterfaceAccount::<TokenAccount>::try_from(&hop[3])?;
+            let token_a_mint = InterfaceAccount::<Mint>::try_from(&hop[4])?;
+            let token_b_mint = InterfaceAccount::<Mint>::try_from(&hop[5])?;
+            let mut observation_buffer = if hop[6].key() == *ctx.program_id {
+                None
+            } else {
+                Some(Account::<ObservationBuffer>::try_from(&hop[6])?)
+            };
+
+            // Reserves are snapshotted per hop, so a pool may only appear once
+            require!(!visited_pools.contains(&pool.key()), ErrorCode::InvalidRoute);
+            visited_pools.push(pool.key());
+            require!(!pool.locked, ErrorCode::PoolLocked);
+            require!(!pool.paused, ErrorCode::PoolPaused);
+
+            // Concentrated pools need tick arrays and are swapped through `swap`
+            require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
+            // Hops carry no oracle accounts, so guarded pools are swapped through `swap` too
+            require!(pool.oracle_config == Pubkey::default(), ErrorCode::InvalidOracle);
+
+            let (expected_authority, authority_bump) = Pubkey::find_program_address(
+                &[b"pool_authority".as_ref(), pool.key().as_ref()],
+                ctx.program_id,
+            );
+            require!(pool_authority.key() == expected_authority, ErrorCode::InvalidRoute);
+            require!(
+                token_a_account.key() == pool.token_a_account
+                    && token_b_account.key() == pool.token_b_account,
+                ErrorCode::InvalidRoute
+            );
+            require!(
+                token_a_mint.key() == pool.token_a_mint && token_b_mint.key() == pool.token_b_mint,
+                ErrorCode::InvalidRoute
+            );
+            if let Some(observation_buffer) = &observation_buffer {
+                require!(observation_buffer.pool == pool.key(), ErrorCode::InvalidRoute);
+            }
+
+            // Accumulate the pre-trade price before reserves change
+            record_price(&mut pool, observation_buffer.as_mut(), clock.unix_timestamp)?;
+            let (reserve_a, reserve_b) = (pool.reserve_a, pool.reserve_b);
+
+            // Determine which token is being swapped in/out
+            let hop_a_to_b = mint_in == pool.token_a_mint;
+            let (vault_in, vault_out, hop_mint_in, hop_mint_out) = if hop_a_to_b {
+                (&mut token_a_account, &token_b_account, &token_a_mint, &token_b_mint)
+            } else {
+                require!(mint_in == pool.token_b_mint, ErrorCode::InvalidRoute);
+                (&mut token_b_account, &token_a_account, &token_b_mint, &token_a_mint)
+            };
+            let (reserve_in, reserve_out) = if hop_a_to_b {
+                (reserve_a, reserve_b)
+            } else {
+                (reserve_b, reserve_a)
+            };
+            let balance_before = vault_in.amount;
+
+            // Move this hop's input into the pool, straight from the previous pool if any
+            match previous_hop.take() {
+                None => {
+                    transfer_from_user(
+                        token_program_for(hop_mint_in),
+                        ctx.accounts.user_token_in.to_account_info(),
+                        vault_in.to_account_info(),
+                        hop_mint_in,
+                        ctx.accounts.user.to_account_info(),
+                        amount,
+                    )?;
+                }
+                Some((previous_vault, previous_authority, previous_pool, previous_bump, _)) => {
+                    transfer_from_pool(
+                        token_program_for(hop_mint_in),
+                        previous_vault,
+                        vault_in.to_account_info(),
+                        hop_mint_in,
+                        previous_authority,
+                        previous_pool,
+                        previous_bump,
+                        amount,
+                    )?;
+                }
+            }
+
+            // Transfer fees mean the pool can receive less than was sent
+            vault_in.reload()?;
+            let amount_received = vault_in.amount.checked_sub(balance_before).unwrap();
+
+            // Calculate the fee
+            let fee = (amount_received as u128)
+                .checked_mul(pool.swap_fee_numerator(clock.unix_timestamp) as u128)
+                .unwrap()
+                .div(pool.fee_denominator as u128) as u64;
+            let amount_in_after_fee = amount_received.checked_sub(fee).unwrap();
+
+            let amount_out = calculate_swap_output(
+                &pool,
+                hop_a_to_b,
+                reserve_in,
+                reserve_out,
+                amount_in_after_fee,
+                clock.unix_timestamp,
+            )
+            .ok_or(ErrorCode::MathOverflow)?;
+            require!(amount_out > 0, ErrorCode::InvalidAmount);
+
+            let reserve_in = reserve_in.checked_add(amount_received).unwrap();
+            let reserve_out = reserve_out.checked_sub(amount_out).unwrap();
+            let reserves_after = if hop_a_to_b {
+                (reserve_in, reserve_out)
+            } else {
+                (reserve_out, reserve_in)
+            };
+            let price_before_x64 =
+                spot_prices_x64(&pool, pool.reserve_a, pool.reserve_b, clock.unix_timestamp)
+                    .map(|(price_a_x64, _)| price_a_x64);
+            let price_after_x64 =
+                spot_prices_x64(&pool, reserves_after.0, reserves_after.1, clock.unix_timestamp)
+                    .map(|(price_a_x64, _)| price_a_x64);
+            pool.check_circuit_breaker(price_before_x64, price_after_x64, clock.slot)?;
+            pool.record_volatility(reserves_after, clock.unix_timestamp)
+                .ok_or(ErrorCode::MathOverflow)?;
+            pool.reserve_a = reserves_after.0;
+            pool.reserve_b = reserves_after.1;
+            emit!(SwapExecuted {
+                pool: pool.key(),
+                user: ctx.accounts.user.key(),
+                mint_in: hop_mint_in.key(),
+                mint_out: hop_mint_out.key(),
+                amount_in: amount_received,
+                amount_out,
+                fee,
+                reserve_a: pool.reserve_a,
+                reserve_b: pool.reserve_b,
+            });
+
+            previous_hop = Some((
+                vault_out.to_account_info(),
+                pool_authority.clone(),
+                pool.key(),
+                authority_bump,
+                hop_mint_out.clone(),
+            ));
+            mint_in = hop_mint_out.key();
+            amount = amount_out;
+
+            // Persist the updated accumulators, reserves and observations
+            pool.exit(ctx.program_id)?;
+            if let Some(observation_buffer) = &observation_buffer {
+                observation_buffer.exit(ctx.program_id)?;
+            }
+        }
+
+        // Check the route ends in the requested token
+        require!(mint_in == ctx.accounts.user_token_out.mint, ErrorCode::InvalidRoute);
+
+        // Check the end-to-end slippage against what the user receives after any transfer fee
+        let (last_vault, last_authority, last_pool, last_bump, last_mint) = previous_hop.unwrap();
+        let amount_received = amount
+            .checked_sub(get_transfer_fee(&last_mint, amount)?)
+            .unwrap();
+        require!(amount_received >= min_amount_out, ErrorCode::SlippageExceeded);
+
+        // Transfer the last hop's output from its pool to the user
+        transfer_from_pool(
+            token_program_for(&last_mint),
+            last_vault,
+            ctx.accounts.user_token_out.to_account_info(),
+            &last_mint,
+            last_authority,
+            last_pool,
+            last_bump,
+            amount,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn initialize_observation_buffer(
+        ctx: Context<InitializeObservationBuffer>,
+        capacity: u16,
+    ) -> Result<()> {
+        require!(
+            capacity > 0 && capacity <= MAX_OBSERVATION_CAPACITY,
+            ErrorCode::InvalidObservationCapacity
+        );
+
+        let observation_buffer = &mut ctx.accounts.observation_buffer;
+        observation_buffer.pool = ctx.accounts.pool.key();
+        observation_buffer.capacity = capacity;
+        observation_buffer.next_index = 0;
+        observation_buffer.observations = Vec::with_capacity(capacity as usize);
+        observation_buffer.bump = *ctx.bumps.get("observation_buffer").unwrap();
+
+        // Seed the buffer so a TWAP is available from initialization onwards
+        let pool = &ctx.accounts.pool;
+        observation_buffer.record(
+            pool.last_observation_ts,
+            pool.price_a_cumulative_x64,
+            pool.price_b_cumulative_x64,
+        );
+
         Ok(())
     }
+
+    pub fn observe(ctx: Context<Observe>, seconds_ago: u32) -> Result<TwapPrice> {
+        require!(seconds_ago > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.pool;
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+
+        // Extend the accumulators to now without writing to the pool
+        let (price_a_cumulative_now, price_b_cumulative_now) = cumulative_prices_at(
+            pool,
+            pool.reserve_a,
+            pool.reserve_b,
+            now,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        let target_ts = now - seconds_ago as i64;
+        let (price_a_cumulative_then, price_b_cumulative_then) = ctx
+            .accounts
+            .observation_buffer
+            .cumulative_prices_at(target_ts, now, price_a_cumulative_now, price_b_cumulative_now)
+            .ok_or(ErrorCode::ObservationTooOld)?;
+
+        // Accumulators wrap, so differences are taken modulo 2^128
+        Ok(TwapPrice {
+            price_a_x64: price_a_cumulative_now.wrapping_sub(price_a_cumulative_then)
+                / seconds_ago as u128,
+            price_b_x64: price_b_cumulative_now.wrapping_sub(price_b_cumulative_then)
+                / seconds_ago as u128,
+            seconds_ago,
+        })
+    }
+
+    pub fn quote_swap(
+        ctx: Context<QuoteSwap>,
+        amount_in: u64,
+        a_to_b: bool,
+    ) -> Result<SwapQuote> {
+        require!(amount_in > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.pool;
+        require!(!pool.paused, ErrorCode::PoolPaused);
+        // Concentrated swaps write to the tick arrays they cross, so they are not quoted here
+        require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
+
+        let clock = Clock::get()?;
+        let reserve_a = pool.reserve_a;
+        let reserve_b = pool.reserve_b;
+        let (reserve_in, reserve_out, mint_in, mint_out) = if a_to_b {
+            (reserve_a, reserve_b, &ctx.accounts.token_a_mint, &ctx.accounts.token_b_mint)
+        } else {
+            (reserve_b, reserve_a, &ctx.accounts.token_b_mint, &ctx.accounts.token_a_mint)
+        };
+
+        // Mirror `swap`, which prices what the pool receives after any transfer fee
+        let amount_received = amount_in
+            .checked_sub(get_transfer_fee(mint_in, amount_in)?)
+            .unwrap();
+        let fee = (amount_received as u128)
+            .checked_mul(pool.swap_fee_numerator(clock.unix_timestamp) as u128)
+            .unwrap()
+            .div(pool.fee_denominator as u128) as u64;
+        let amount_in_after_fee = amount_received.checked_sub(fee).unwrap();
+        let amount_out = calculate_swap_output(
+            pool,
+            a_to_b,
+            reserve_in,
+            reserve_out,
+            amount_in_after_fee,
+            clock.unix_timestamp,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        // Price impact compares the execution price, net of fees, with the spot price
+        let (price_a_x64, price_b_x64) =
+            spot_prices_x64(pool, reserve_a, reserve_b, clock.unix_timestamp)
+                .ok_or(ErrorCode::EmptyPool)?;
+        let impact_bps = price_impact_bps(
+            if a_to_b { price_a_x64 } else { price_b_x64 },
+            amount_in_after_fee,
+            amount_out,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        let reserve_in = reserve_in.checked_add(amount_received).unwrap();
+        let reserve_out = reserve_out.checked_sub(amount_out).unwrap();
+        let (reserve_a, reserve_b) = if a_to_b {
+            (reserve_in, reserve_out)
+        } else {
+            (reserve_out, reserve_in)
+        };
+
+        Ok(SwapQuote {
+            amount_in,
+            amount_out: amount_out
+                .checked_sub(get_transfer_fee(mint_out, amount_out)?)
+                .unwrap(),
+            fee,
+            price_impact_bps: impact_bps,
+            reserve_a,
+            reserve_b,
+        })
+    }
+
+    pub fn quote_add_liquidity(
+        ctx: Context<QuoteAddLiquidity>,
+        amount_a: u64,
+        amount_b: u64,
+    ) -> Result<LiquidityQuote> {
+        require!(amount_a > 0 && amount_b > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.pool;
+        require!(!pool.paused, ErrorCode::PoolPaused);
+        require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
+
+        let clock = Clock::get()?;
+        let reserve_a = pool.reserve_a;
+        let reserve_b = pool.reserve_b;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+
+        // Mirror `add_liquidity`, which prices what the pool receives after any transfer fees
+        let amount_a = amount_a
+            .checked_sub(get_transfer_fee(&ctx.accounts.token_a_mint, amount_a)?)
+            .unwrap();
+        let amount_b = amount_b
+            .checked_sub(get_transfer_fee(&ctx.accounts.token_b_mint, amount_b)?)
+            .unwrap();
+        let mut lp_tokens = calculate_lp_tokens(
+            pool,
+            reserve_a,
+            reserve_b,
+            amount_a,
+            amount_b,
+            lp_supply,
+            clock.unix_timestamp,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        if lp_supply == 0 {
+            require!(
+                amount_a >= pool.min_initial_deposit_a && amount_b >= pool.min_initial_deposit_b,
+                ErrorCode::InitialDepositTooSmall
+            );
+            require!(lp_tokens > MINIMUM_LIQUIDITY, ErrorCode::InitialDepositTooSmall);
+            lp_tokens -= MINIMUM_LIQUIDITY;
+        }
+
+        // Only stable pools charge a fee on deposits, in LP tokens on the imbalanced part
+        let fee = if pool.pool_type == PoolType::StableSwap && lp_supply > 0 {
+            let mut pool_without_fee = Pool::clone(pool);
+            pool_without_fee.fee_numerator = 0;
+            calculate_lp_tokens(
+                &pool_without_fee,
+                reserve_a,
+                reserve_b,
+                amount_a,
+                amount_b,
+                lp_supply,
+                clock.unix_timestamp,
+            )
+            .ok_or(ErrorCode::MathOverflow)?
+            .saturating_sub(lp_tokens)
+        } else {
+            0
+        };
+
+        let reserve_a_after = reserve_a.checked_add(amount_a).unwrap();
+        let reserve_b_after = reserve_b.checked_add(amount_b).unwrap();
+        let impact_bps = price_change_bps(
+            pool,
+            (reserve_a, reserve_b),
+            (reserve_a_after, reserve_b_after),
+            clock.unix_timestamp,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(LiquidityQuote {
+            amount_a,
+            amount_b,
+            lp_tokens,
+            fee,
+            price_impact_bps: impact_bps,
+            reserve_a: reserve_a_after,
+            reserve_b: reserve_b_after,
+        })
+    }
+
+    pub fn quote_remove_liquidity(
+        ctx: Context<QuoteRemoveLiquidity>,
+        lp_amount: u64,
+    ) -> Result<LiquidityQuote> {
+        require!(lp_amount > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.pool;
+        require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
+
+        let clock = Clock::get()?;
+        let reserve_a = pool.reserve_a;
+        let reserve_b = pool.reserve_b;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        require!(lp_amount <= lp_supply, ErrorCode::InvalidAmount);
+
+        // Mirror `remove_liquidity`, which pays out a proportional share
+        let amount_a = (lp_amount as u128)
+            .checked_mul(reserve_a as u128)
+            .unwrap()
+            .div(lp_supply as u128) as u64;
+        let amount_b = (lp_amount as u128)
+            .checked_mul(reserve_b as u128)
+            .unwrap()
+            .div(lp_supply as u128) as u64;
+
+        let reserve_a_after = reserve_a.checked_sub(amount_a).unwrap();
+        let reserve_b_after = reserve_b.checked_sub(amount_b).unwrap();
+        let impact_bps = price_change_bps(
+            pool,
+            (reserve_a, reserve_b),
+            (reserve_a_after, reserve_b_after),
+            clock.unix_timestamp,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        // Report what arrives in the user's accounts after any transfer fees
+        Ok(LiquidityQuote {
+            amount_a: amount_a
+                .checked_sub(get_transfer_fee(&ctx.accounts.token_a_mint, amount_a)?)
+                .unwrap(),
+            amount_b: amount_b
+                .checked_sub(get_transfer_fee(&ctx.accounts.token_b_mint, amount_b)?)
+                .unwrap(),
+            lp_tokens: lp_amount,
+            fee: 0,
+            price_impact_bps: impact_bps,
+            reserve_a: reserve_a_after,
+            reserve_b: reserve_b_after,
+        })
+    }
+
+    pub fn flash_loan<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashLoan<'info>>,
+        amount_a: u64,
+        amount_b: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require!(amount_a > 0 || amount_b > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+        require!(!ctx.accounts.pool.paused, ErrorCode::PoolPaused);
+        require!(
+            ctx.accounts.borrower_program.key() != *ctx.program_id,
+            ErrorCode::InvalidBorrowerProgram
+        );
+
+        let pool = &ctx.accounts.pool;
+        let balance_a = ctx.accounts.token_a_account.amount;
+        let balance_b = ctx.accounts.token_b_account.amount;
+        require!(
+            amount_a <= balance_a && amount_b <= balance_b,
+            ErrorCode::InsufficientLiquidity
+        );
+
+        // The flash fee is the pool's swap fee, rounded up in the pool's favor
+        let fee_a = calculate_flash_fee(pool, amount_a).ok_or(ErrorCode::MathOverflow)?;
+        let fee_b = calculate_flash_fee(pool, amount_b).ok_or(ErrorCode::MathOverflow)?;
+
+        // Lock the pool and persist the flag before handing over control
+        ctx.accounts.pool.locked = true;
+        ctx.accounts.pool.exit(ctx.program_id)?;
+
+        let pool = &ctx.accounts.pool;
+
+        // Transfer the borrowed tokens from pool to borrower
+        if amount_a > 0 {
+            transfer_from_pool(
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                ctx.accounts.borrower_token_a.to_account_info(),
+                &ctx.accounts.token_a_mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                amount_a,
+            )?;
+        }
+
+        if amount_b > 0 {
+            transfer_from_pool(
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                ctx.accounts.borrower_token_b.to_account_info(),
+                &ctx.accounts.token_b_mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                amount_b,
+            )?;
+        }
+
+        // Call back into the borrower with the remaining accounts and the caller's data
+        let account_metas = ctx
+            .remaining_accounts
+            .iter()
+            .map(|account| {
+                if account.is_writable {
+                    AccountMeta::new(account.key(), account.is_signer)
+                } else {
+                    AccountMeta::new_readonly(account.key(), account.is_signer)
+                }
+            })
+            .collect();
+        let callback = Instruction {
+            program_id: ctx.accounts.borrower_program.key(),
+            accounts: account_metas,
+            data,
+        };
+        let mut callback_accounts = ctx.remaining_accounts.to_vec();
+        callback_accounts.push(ctx.accounts.borrower_program.to_account_info());
+        invoke(&callback, &callback_accounts)?;
+
+        // Verify the loan was repaid with the fee, measured at the vaults so any
+        // transfer fees on the way back are borne by the borrower
+        ctx.accounts.token_a_account.reload()?;
+        ctx.accounts.token_b_account.reload()?;
+        require!(
+            ctx.accounts.token_a_account.amount
+                >= balance_a.checked_add(fee_a).ok_or(ErrorCode::MathOverflow)?,
+            ErrorCode::FlashLoanNotRepaid
+        );
+        require!(
+            ctx.accounts.token_b_account.amount
+                >= balance_b.checked_add(fee_b).ok_or(ErrorCode::MathOverflow)?,
+            ErrorCode::FlashLoanNotRepaid
+        );
+
+        let pool = &mut ctx.accounts.pool;
+        pool.reserve_a = pool.reserve_a.checked_add(fee_a).ok_or(ErrorCode::MathOverflow)?;
+        pool.reserve_b = pool.reserve_b.checked_add(fee_b).ok_or(ErrorCode::MathOverflow)?;
+
+        // Concentrated pools distribute the fee to in-range liquidity
+        if pool.pool_type == PoolType::Concentrated && pool.liquidity > 0 {
+            pool.fee_growth_global_a_x64 = pool
+                .fee_growth_global_a_x64
+                .wrapping_add(((fee_a as u128) << 64) / pool.liquidity);
+            pool.fee_growth_global_b_x64 = pool
+                .fee_growth_global_b_x64
+                .wrapping_add(((fee_b as u128) << 64) / pool.liquidity);
+        }
+
+        pool.locked = false;
+
+        emit!(FlashLoanExecuted {
+            pool: pool.key(),
+            borrower: ctx.accounts.borrower.key(),
+            amount_a,
+            amount_b,
+            fee_a,
+            fee_b,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn skim(ctx: Context<Skim>) -> Result<()> {
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+
+        // Anything in the vaults beyond the tracked reserves was donated and is not priced
+        let pool = &ctx.accounts.pool;
+        let excess_a = ctx.accounts.token_a_account.amount.saturating_sub(pool.reserve_a);
+        let excess_b = ctx.accounts.token_b_account.amount.saturating_sub(pool.reserve_b);
+
+        if excess_a > 0 {
+            transfer_from_pool(
+                ctx.accounts.token_a_program.to_account_info(),
+                ctx.accounts.token_a_account.to_account_info(),
+                ctx.accounts.recipient_token_a.to_account_info(),
+                &ctx.accounts.token_a_mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                excess_a,
+            )?;
+        }
+
+        if excess_b > 0 {
+            transfer_from_pool(
+                ctx.accounts.token_b_program.to_account_info(),
+                ctx.accounts.token_b_account.to_account_info(),
+                ctx.accounts.recipient_token_b.to_account_info(),
+                &ctx.accounts.token_b_mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                excess_b,
+            )?;
+        }
+
+        emit!(ExcessSkimmed {
+            pool: pool.key(),
+            recipient_token_a: ctx.accounts.recipient_token_a.key(),
+            recipient_token_b: ctx.accounts.recipient_token_b.key(),
+            amount_a: excess_a,
+            amount_b: excess_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn sync(ctx: Context<Sync>) -> Result<()> {
+        require!(!ctx.accounts.pool.locked, ErrorCode::PoolLocked);
+
+        // Close out the old reserves' price before they are replaced
+        record_price(&mut ctx.accounts.pool, None, Clock::get()?.unix_timestamp)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.reserve_a = ctx.accounts.token_a_account.amount;
+        pool.reserve_b = ctx.accounts.token_b_account.amount;
+
+        emit!(ReservesSynced {
+            pool: pool.key(),
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_pool_fee(
+        ctx: Context<SetPoolFee>,
+        fee_numerator: u64,
+        fee_denominator: u64,
+    ) -> Result<()> {
+        require!(fee_denominator > 0, ErrorCode::InvalidFee);
+        require!(fee_numerator < fee_denominator, ErrorCode::InvalidFee);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.fee_numerator = fee_numerator;
+        pool.fee_denominator = fee_denominator;
+
+        Ok(())
+    }
+
+    pub fn set_dynamic_fee(
+        ctx: Context<SetDynamicFee>,
+        enabled: bool,
+        volatility_fee_factor_bps: u16,
+        max_volatility_bps: u32,
+        max_dynamic_fee_bps: u16,
+        volatility_decay_period: i64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        if enabled {
+            // Concentrated pools charge their fee tick by tick and keep it fixed
+            require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
+            require!(volatility_decay_period > 0, ErrorCode::InvalidFee);
+            require!(
+                (max_dynamic_fee_bps as u64) < FEE_TIER_DENOMINATOR,
+                ErrorCode::InvalidFee
+            );
+            // The cap may not undercut the base fee
+            require!(
+                (pool.fee_numerator as u128) * FEE_TIER_DENOMINATOR as u128
+                    <= max_dynamic_fee_bps as u128 * pool.fee_denominator as u128,
+                ErrorCode::InvalidFee
+            );
+        }
+
+        pool.dynamic_fee_enabled = enabled;
+        pool.volatility_fee_factor_bps = volatility_fee_factor_bps;
+        pool.max_volatility_bps = max_volatility_bps;
+        pool.max_dynamic_fee_bps = max_dynamic_fee_bps;
+        pool.volatility_decay_period = volatility_decay_period;
+        pool.volatility_bps = 0;
+        pool.volatility_updated_ts = Clock::get()?.unix_timestamp;
+
+        Ok(())
+    }
+
+    pub fn set_oracle_guard(
+        ctx: Context<SetOracleGuard>,
+        max_oracle_deviation_bps: u16,
+        oracle_stale_policy: OracleStalePolicy,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        // Leaving out the oracle config turns the guard off. PMM pools also price around
+        // the linked oracle, so they may link one without a deviation limit
+        match &ctx.accounts.oracle_config {
+            Some(oracle_config) => {
+                require!(
+                    max_oracle_deviation_bps > 0 || pool.pool_type == PoolType::Pmm,
+                    ErrorCode::InvalidOracle
+                );
+                require!(max_oracle_deviation_bps <= MAX_PRICE_GUARD_BPS, ErrorCode::InvalidOracle);
+                pool.oracle_config = oracle_config.key();
+            }
+            None => {
+                require!(max_oracle_deviation_bps == 0, ErrorCode::InvalidOracle);
+                pool.oracle_config = Pubkey::default();
+            }
+        }
+        pool.max_oracle_deviation_bps = max_oracle_deviation_bps;
+        pool.oracle_stale_policy = oracle_stale_policy;
+
+        Ok(())
+    }
+
+    pub fn set_circuit_breaker(
+        ctx: Context<SetCircuitBreaker>,
+        enabled: bool,
+        max_price_impact_bps: u16,
+        max_slot_price_move_bps: u16,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        if enabled {
+            require!(
+                max_price_impact_bps > 0 && max_price_impact_bps <= MAX_PRICE_GUARD_BPS,
+                ErrorCode::InvalidCircuitBreaker
+            );
+            require!(
+                max_slot_price_move_bps > 0 && max_slot_price_move_bps <= MAX_PRICE_GUARD_BPS,
+                ErrorCode::InvalidCircuitBreaker
+            );
+            pool.max_price_impact_bps = max_price_impact_bps;
+            pool.max_slot_price_move_bps = max_slot_price_move_bps;
+        } else {
+            // Zero limits switch both checks off
+            pool.max_price_impact_bps = 0;
+            pool.max_slot_price_move_bps = 0;
+        }
+
+        Ok(())
+    }
+
+    pub fn pause_pool(ctx: Context<PausePool>) -> Result<()> {
+        ctx.accounts.pool.paused = true;
+
+        Ok(())
+    }
+
+    pub fn unpause_pool(ctx: Context<UnpausePool>) -> Result<()> {
+        ctx.accounts.pool.paused = false;
+
+        Ok(())
+    }
+
+    pub fn propose_authority(
+        ctx: Context<ProposeAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        // Proposing the default pubkey cancels a pending handover
+        ctx.accounts.pool.pending_authority = new_authority;
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.authority = ctx.accounts.new_authority.key();
+        pool.pending_authority = Pubkey::default();
+
+        Ok(())
+    }
+
+    pub fn initialize_fee_tier_registry(ctx: Context<InitializeFeeTierRegistry>) -> Result<()> {
+        let fee_tier_registry = &mut ctx.accounts.fee_tier_registry;
+        fee_tier_registry.authority = ctx.accounts.authority.key();
+        fee_tier_registry.fee_tiers = DEFAULT_FEE_TIERS_BPS.to_vec();
+        fee_tier_registry.bump = *ctx.bumps.get("fee_tier_registry").unwrap();
+
+        Ok(())
+    }
+
+    pub fn add_fee_tier(ctx: Context<AddFeeTier>, fee_tier_bps: u16) -> Result<()> {
+        require!(
+            fee_tier_bps > 0 && (fee_tier_bps as u64) < FEE_TIER_DENOMINATOR,
+            ErrorCode::InvalidFeeTier
+        );
+
+        let fee_tier_registry = &mut ctx.accounts.fee_tier_registry;
+        require!(
+            !fee_tier_registry.fee_tiers.contains(&fee_tier_bps),
+            ErrorCode::InvalidFeeTier
+        );
+        require!(
+            fee_tier_registry.fee_tiers.len() < MAX_FEE_TIERS,
+            ErrorCode::FeeTierRegistryFull
+        );
+
+        fee_tier_registry.fee_tiers.push(fee_tier_bps);
+        fee_tier_registry.fee_tiers.sort_unstable();
+
+        Ok(())
+    }
+
+    pub fn remove_fee_tier(ctx: Context<RemoveFeeTier>, fee_tier_bps: u16) -> Result<()> {
+        // Existing pools at the tier keep trading, only new pools are affected
+        let fee_tier_registry = &mut ctx.accounts.fee_tier_registry;
+        let index = fee_tier_registry
+            .fee_tiers
+            .iter()
+            .position(|tier| *tier == fee_tier_bps)
+            .ok_or(ErrorCode::InvalidFeeTier)?;
+        fee_tier_registry.fee_tiers.remove(index);
+
+        Ok(())
+    }
+
+    pub fn initialize_pool_registry(ctx: Context<InitializePoolRegistry>) -> Result<()> {
+        let pool_registry = &mut ctx.accounts.pool_registry;
+        pool_registry.pool_count = 0;
+        pool_registry.page_count = 1;
+        pool_registry.bump = *ctx.bumps.get("pool_registry").unwrap();
+
+        let pool_registry_page = &mut ctx.accounts.pool_registry_page;
+        pool_registry_page.index = 0;
+        pool_registry_page.pools = Vec::with_capacity(POOLS_PER_REGISTRY_PAGE);
+        pool_registry_page.bump = *ctx.bumps.get("pool_registry_page").unwrap();
+
+        Ok(())
+    }
+
+    pub fn add_pool_registry_page(ctx: Context<AddPoolRegistryPage>) -> Result<()> {
+        let pool_registry = &mut ctx.accounts.pool_registry;
+        // A new page is only needed once every existing page is full
+        require!(
+            pool_registry.pool_count
+                >= pool_registry.page_count as u64 * POOLS_PER_REGISTRY_PAGE as u64,
+            ErrorCode::InvalidRegistryPage
+        );
+
+        let pool_registry_page = &mut ctx.accounts.pool_registry_page;
+        pool_registry_page.index = pool_registry.page_count;
+        pool_registry_page.pools = Vec::with_capacity(POOLS_PER_REGISTRY_PAGE);
+        pool_registry_page.bump = *ctx.bumps.get("pool_registry_page").unwrap();
+
+        pool_registry.page_count = pool_registry.page_count.checked_add(1).unwrap();
+
+        Ok(())
+    }
+
+    pub fn initialize_referrer_stats(ctx: Context<InitializeReferrerStats>) -> Result<()> {
+        let referrer_stats = &mut ctx.accounts.referrer_stats;
+        referrer_stats.referrer = ctx.accounts.referrer.key();
+        referrer_stats.mint = ctx.accounts.mint.key();
+        referrer_stats.bump = *ctx.bumps.get("referrer_stats").unwrap();
+
+        Ok(())
+    }
+
+    pub fn initialize_multi_pool<'info>(
+        ctx: Context<'_, '_, 'info, 'info, InitializeMultiPool<'info>>,
+        fee_tier_bps: u16,
+        pool_type: PoolType,
+        amp: u64,
+    ) -> Result<()> {
+        // Constant product extends to the product of every reserve, stable pools to the
+        // n-coin StableSwap invariant
+        match pool_type {
+            PoolType::ConstantProduct => {}
+            PoolType::StableSwap => {
+                require!(amp >= MIN_AMP && amp <= MAX_AMP, ErrorCode::InvalidAmp);
+            }
+            _ => return err!(ErrorCode::InvalidPoolType),
+        }
+        require!(
+            ctx.accounts.fee_tier_registry.fee_tiers.contains(&fee_tier_bps),
+            ErrorCode::InvalidFeeTier
+        );
+
+        // Member mints are passed in ascending order, which also rules out duplicates
+        let mints = ctx.remaining_accounts;
+        require!(
+            mints.len() >= MIN_MULTI_POOL_TOKENS && mints.len() <= MAX_MULTI_POOL_TOKENS,
+            ErrorCode::InvalidMultiPoolTokens
+        );
+        let decimals = InterfaceAccount::<Mint>::try_from(&mints[0])?.decimals;
+        let pool = &mut ctx.accounts.pool;
+        for (index, mint_info) in mints.iter().enumerate() {
+            let mint = InterfaceAccount::<Mint>::try_from(mint_info)?;
+            validate_mint_extensions(&mint)?;
+            if index > 0 {
+                require!(pool.mints[index - 1] < mint.key(), ErrorCode::InvalidMintOrder);
+            }
+            // The stable invariant compares raw balances, so all members must share decimals
+            if pool_type == PoolType::StableSwap {
+                require!(mint.decimals == decimals, ErrorCode::DecimalsMismatch);
+            }
+            pool.mints[index] = mint.key();
+        }
+
+        pool.authority = ctx.accounts.authority.key();
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.pool_type = pool_type;
+        pool.amp = amp;
+        pool.fee_numerator = fee_tier_bps as u64;
+        pool.fee_denominator = FEE_TIER_DENOMINATOR;
+        pool.fee_tier_bps = fee_tier_bps;
+        pool.token_count = mints.len() as u8;
+        pool.authority_bump = *ctx.bumps.get("pool_authority").unwrap();
+
+        Ok(())
+    }
+
+    pub fn initialize_multi_pool_vault(
+        ctx: Context<InitializeMultiPoolVault>,
+        index: u8,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.vaults[index as usize] = ctx.accounts.vault.key();
+
+        Ok(())
+    }
+
+    pub fn swap_multi(
+        ctx: Context<SwapMulti>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        require!(amount_in > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.pool;
+        require!(pool.is_ready(), ErrorCode::MultiPoolNotReady);
+        let index_in = pool
+            .member_index(ctx.accounts.vault_in.key())
+            .ok_or(ErrorCode::InvalidMultiPoolAccounts)?;
+        let index_out = pool
+            .member_index(ctx.accounts.vault_out.key())
+            .ok_or(ErrorCode::InvalidMultiPoolAccounts)?;
+        require!(index_in != index_out, ErrorCode::InvalidMultiPoolAccounts);
+
+        // Transfer token in from user to pool
+        let balance_before = ctx.accounts.vault_in.amount;
+        transfer_from_user(
+            ctx.accounts.token_program_in.to_account_info(),
+            ctx.accounts.user_token_in.to_account_info(),
+            ctx.accounts.vault_in.to_account_info(),
+            &ctx.accounts.mint_in,
+            ctx.accounts.user.to_account_info(),
+            amount_in,
+        )?;
+
+        // Transfer fees mean the pool can receive less than was sent
+        ctx.accounts.vault_in.reload()?;
+        let amount_received = ctx.accounts.vault_in.amount.checked_sub(balance_before).unwrap();
+
+        let fee = (amount_received as u128)
+            .checked_mul(pool.fee_numerator as u128)
+            .unwrap()
+            .div(pool.fee_denominator as u128) as u64;
+        let amount_out = calculate_multi_swap_output(
+            pool,
+            index_in,
+            index_out,
+            amount_received.checked_sub(fee).unwrap(),
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        require!(amount_out > 0, ErrorCode::InvalidAmount);
+
+        // Check slippage against what the user receives after any transfer fee
+        let amount_sent = amount_out
+            .checked_sub(get_transfer_fee(&ctx.accounts.mint_out, amount_out)?)
+            .unwrap();
+        require!(amount_sent >= min_amount_out, ErrorCode::SlippageExceeded);
+
+        transfer_from_multi_pool(
+            ctx.accounts.token_program_out.to_account_info(),
+            ctx.accounts.vault_out.to_account_info(),
+            ctx.accounts.user_token_out.to_account_info(),
+            &ctx.accounts.mint_out,
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
+            amount_out,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.reserves[index_in] = pool.reserves[index_in].checked_add(amount_received).unwrap();
+        pool.reserves[index_out] = pool.reserves[index_out].checked_sub(amount_out).unwrap();
+
+        emit!(MultiPoolSwapExecuted {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            mint_in: ctx.accounts.mint_in.key(),
+            mint_out: ctx.accounts.mint_out.key(),
+            amount_in: amount_received,
+            amount_out,
+            fee,
+            reserves: pool.active_reserves().to_vec(),
+        });
+
+        Ok(())
+    }
+
+    pub fn add_multi_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AddMultiLiquidity<'info>>,
+        amounts: Vec<u64>,
+        min_lp_tokens: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let token_count = pool.token_count as usize;
+        require!(pool.is_ready(), ErrorCode::MultiPoolNotReady);
+        require!(
+            amounts.len() == token_count && amounts.iter().all(|amount| *amount > 0),
+            ErrorCode::InvalidAmount
+        );
+        let members = ctx.remaining_accounts;
+        require!(
+            members.len() == token_count * MULTI_POOL_ACCOUNTS_PER_TOKEN,
+            ErrorCode::InvalidMultiPoolAccounts
+        );
+
+        // Members may mix SPL Token and Token-2022 mints, each moved by the program that owns it
+        let token_program_for = |mint: &InterfaceAccount<'info, Mint>| {
+            if *mint.to_account_info().owner == Token::id() {
+                ctx.accounts.token_program.to_account_info()
+            } else {
+                ctx.accounts.token_2022_program.to_account_info()
+            }
+        };
+
+        // Transfer every member from the user, pricing LP tokens on what actually arrived
+        let mut received = Vec::with_capacity(token_count);
+        for (index, member) in members.chunks(MULTI_POOL_ACCOUNTS_PER_TOKEN).enumerate() {
+            let mint = InterfaceAccount::<Mint>::try_from(&member[0])?;
+            let mut vault = InterfaceAccount::<TokenAccount>::try_from(&member[1])?;
+            require!(
+                mint.key() == pool.mints[index] && vault.key() == pool.vaults[index],
+                ErrorCode::InvalidMultiPoolAccounts
+            );
+
+            let balance_before = vault.amount;
+            transfer_from_user(
+                token_program_for(&mint),
+                member[2].clone(),
+                vault.to_account_info(),
+                &mint,
+                ctx.accounts.user.to_account_info(),
+                amounts[index],
+            )?;
+            vault.reload()?;
+            received.push(vault.amount.checked_sub(balance_before).unwrap());
+        }
+        require!(received.iter().all(|amount| *amount > 0), ErrorCode::InvalidAmount);
+
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        let lp_token_program = token_program_for(&ctx.accounts.lp_mint);
+        let mut lp_tokens =
+            calculate_multi_lp_tokens(pool, &received, lp_supply).ok_or(ErrorCode::MathOverflow)?;
+
+        // Lock the minimum liquidity on the first deposit, as two-token pools do
+        if lp_supply == 0 {
+            require!(lp_tokens > MINIMUM_LIQUIDITY, ErrorCode::InitialDepositTooSmall);
+            mint_multi_pool_lp(
+                lp_token_program.clone(),
+                ctx.accounts.lp_mint.to_account_info(),
+                ctx.accounts.locked_lp_token.to_account_info(),
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                MINIMUM_LIQUIDITY,
+            )?;
+            lp_tokens -= MINIMUM_LIQUIDITY;
+        }
+        require!(lp_tokens >= min_lp_tokens, ErrorCode::SlippageExceeded);
+
+        mint_multi_pool_lp(
+            lp_token_program,
+            ctx.accounts.lp_mint.to_account_info(),
+            ctx.accounts.user_lp_token.to_account_info(),
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
+            lp_tokens,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        for (index, amount) in received.iter().enumerate() {
+            pool.reserves[index] = pool.reserves[index].checked_add(*amount).unwrap();
+        }
+
+        emit!(MultiPoolLiquidityAdded {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amounts: received,
+            lp_tokens,
+            reserves: pool.active_reserves().to_vec(),
+        });
+
+        Ok(())
+    }
+
+    pub fn add_multi_liquidity_single_sided(
+        ctx: Context<AddMultiLiquiditySingleSided>,
+        amount_in: u64,
+        min_lp_tokens: u64,
+    ) -> Result<()> {
+        require!(amount_in > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.pool;
+        require!(pool.is_ready(), ErrorCode::MultiPoolNotReady);
+        let index = pool
+            .member_index(ctx.accounts.vault.key())
+            .ok_or(ErrorCode::InvalidMultiPoolAccounts)?;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+
+        // The pool must already be priced by a deposit of every member
+        require!(lp_supply > 0, ErrorCode::EmptyPool);
+
+        let balance_before = ctx.accounts.vault.amount;
+        transfer_from_user(
+            ctx.accounts.token_program_in.to_account_info(),
+            ctx.accounts.user_token_in.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.mint,
+            ctx.accounts.user.to_account_info(),
+            amount_in,
+        )?;
+
+        // Transfer fees mean the pool can receive less than was sent
+        ctx.accounts.vault.reload()?;
+        let amount_in = ctx.accounts.vault.amount.checked_sub(balance_before).unwrap();
+
+        let lp_tokens = match pool.pool_type {
+            // An equal-weight product pool prices the deposit like a weighted pool with 1/n weights
+            PoolType::ConstantProduct => calculate_weighted_single_sided_lp_tokens(
+                pool.fee_numerator,
+                pool.fee_denominator,
+                pool.reserves[index],
+                WEIGHT_ONE / pool.token_count as u64,
+                amount_in,
+                lp_supply,
+            ),
+            // The stable deposit math already charges the imbalance fee
+            _ => {
+                let mut amounts = vec![0; pool.token_count as usize];
+                amounts[index] = amount_in;
+                calculate_multi_lp_tokens(pool, &amounts, lp_supply)
+            }
+        }
+        .ok_or(ErrorCode::MathOverflow)?;
+        require!(lp_tokens > 0, ErrorCode::InvalidAmount);
+        require!(lp_tokens >= min_lp_tokens, ErrorCode::SlippageExceeded);
+
+        mint_multi_pool_lp(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.lp_mint.to_account_info(),
+            ctx.accounts.user_lp_token.to_account_info(),
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
+            lp_tokens,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.reserves[index] = pool.reserves[index].checked_add(amount_in).unwrap();
+
+        let mut amounts = vec![0; pool.token_count as usize];
+        amounts[index] = amount_in;
+        emit!(MultiPoolLiquidityAdded {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amounts,
+            lp_tokens,
+            reserves: pool.active_reserves().to_vec(),
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_multi_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RemoveMultiLiquidity<'info>>,
+        lp_amount: u64,
+        min_amounts: Vec<u64>,
+    ) -> Result<()> {
+        require!(lp_amount > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.pool;
+        let token_count = pool.token_count as usize;
+        require!(min_amounts.len() == token_count, ErrorCode::InvalidAmount);
+        let members = ctx.remaining_accounts;
+        require!(
+            members.len() == token_count * MULTI_POOL_ACCOUNTS_PER_TOKEN,
+            ErrorCode::InvalidMultiPoolAccounts
+        );
+
+        let token_program_for = |mint: &InterfaceAccount<'info, Mint>| {
+            if *mint.to_account_info().owner == Token::id() {
+                ctx.accounts.token_program.to_account_info()
+            } else {
+                ctx.accounts.token_2022_program.to_account_info()
+            }
+        };
+
+        // Burn LP tokens
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        token_interface::burn(
+            CpiContext::new(
+                token_program_for(&ctx.accounts.lp_mint),
+                token_interface::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_token.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+
+        // Proportional withdrawals leave the price unchanged under both invariants
+        let mut amounts = Vec::with_capacity(token_count);
+        for (index, member) in members.chunks(MULTI_POOL_ACCOUNTS_PER_TOKEN).enumerate() {
+            let mint = InterfaceAccount::<Mint>::try_from(&member[0])?;
+            require!(
+                mint.key() == pool.mints[index] && member[1].key() == pool.vaults[index],
+                ErrorCode::InvalidMultiPoolAccounts
+            );
+
+            let amount = (lp_amount as u128)
+                .checked_mul(pool.reserves[index] as u128)
+                .unwrap()
+                .div(lp_supply as u128) as u64;
+
+            // Check slippage against what the user receives after any transfer fee
+            let received = amount.checked_sub(get_transfer_fee(&mint, amount)?).unwrap();
+            require!(received >= min_amounts[index], ErrorCode::SlippageExceeded);
+
+            transfer_from_multi_pool(
+                token_program_for(&mint),
+                member[1].clone(),
+                member[2].clone(),
+                &mint,
+                ctx.accounts.pool_authority.to_account_info(),
+                pool.key(),
+                pool.authority_bump,
+                amount,
+            )?;
+            amounts.push(amount);
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        for (index, amount) in amounts.iter().enumerate() {
+            pool.reserves[index] = pool.reserves[index].checked_sub(*amount).unwrap();
+        }
+
+        emit!(MultiPoolLiquidityRemoved {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amounts,
+            lp_tokens: lp_amount,
+            reserves: pool.active_reserves().to_vec(),
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_multi_liquidity_single_sided(
+        ctx: Context<RemoveMultiLiquiditySingleSided>,
+        lp_amount: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        require!(lp_amount > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.pool;
+        require!(pool.is_ready(), ErrorCode::MultiPoolNotReady);
+        let index = pool
+            .member_index(ctx.accounts.vault.key())
+            .ok_or(ErrorCode::InvalidMultiPoolAccounts)?;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+
+        let amount_out = match pool.pool_type {
+            PoolType::ConstantProduct => calculate_weighted_single_sided_withdrawal(
+                pool.fee_numerator,
+                pool.fee_denominator,
+                pool.reserves[index],
+                WEIGHT_ONE / pool.token_count as u64,
+                lp_amount,
+                lp_supply,
+            ),
+            _ => calculate_stable_multi_withdraw_one(pool, index, lp_amount, lp_supply),
+        }
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        // Check slippage against what the user receives after any transfer fee
+        let amount_received = amount_out
+            .checked_sub(get_transfer_fee(&ctx.accounts.mint, amount_out)?)
+            .unwrap();
+        require!(amount_received >= min_amount_out, ErrorCode::SlippageExceeded);
+
+        // Burn LP tokens
+        token_interface::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_token.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+
+        transfer_from_multi_pool(
+            ctx.accounts.token_program_out.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.user_token_out.to_account_info(),
+            &ctx.accounts.mint,
+            ctx.accounts.pool_authority.to_account_info(),
+            pool.key(),
+            pool.authority_bump,
+            amount_out,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.reserves[index] = pool.reserves[index].checked_sub(amount_out).unwrap();
+
+        let mut amounts = vec![0; pool.token_count as usize];
+        amounts[index] = amount_out;
+        emit!(MultiPoolLiquidityRemoved {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            amounts,
+            lp_tokens: lp_amount,
+            reserves: pool.active_reserves().to_vec(),
+        });
+
+        Ok(())
+    }
+
+    pub fn create_launch_pool(
+        ctx: Context<CreateLaunchPool>,
+        fee_tier_bps: u16,
+        token_amount: u64,
+        virtual_token_reserve: u64,
+        virtual_quote_reserve: u64,
+        graduation_market_cap: u64,
+        lp_disposition: LpDisposition,
+    ) -> Result<()> {
+        require!(token_amount > 0 && virtual_quote_reserve > 0, ErrorCode::InvalidAmount);
+
+        // The constant product pool the reserves migrate into is created with the launch,
+        // pairing the launch token with its quote mint in whichever order they sort
+        let pool_accounts = &ctx.accounts.pool_accounts;
+        let (token_mint, quote_mint) =
+            (ctx.accounts.token_mint.key(), ctx.accounts.quote_mint.key());
+        let pool_mints = (pool_accounts.token_a_mint.key(), pool_accounts.token_b_mint.key());
+        require!(
+            pool_mints == (token_mint, quote_mint) || pool_mints == (quote_mint, token_mint),
+            ErrorCode::InvalidLaunchPool
+        );
+        let bump = *ctx.bumps.get("pool").unwrap();
+        let authority_bump = *ctx.bumps.get("pool_authority").unwrap();
+        write_pool_state(
+            &mut ctx.accounts.pool_accounts,
+            bump,
+            authority_bump,
+            fee_tier_bps,
+            PoolType::ConstantProduct,
+        )?;
+
+        // The pool stays paused until the migration seeds it, so nobody can deposit at a
+        // price of their choosing first. Nothing signs as the launch authority outside
+        // this program, so the pool is deliberately immutable: nobody can pause it again
+        // or change its fee, oracle guard or circuit breaker
+        let pool = &mut ctx.accounts.pool_accounts.pool;
+        pool.authority = ctx.accounts.launch_authority.key();
+        pool.paused = true;
+        let pool_key = pool.key();
+
+        transfer_from_user(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.creator_token_account.to_account_info(),
+            ctx.accounts.token_vault.to_account_info(),
+            &ctx.accounts.token_mint,
+            ctx.accounts.creator.to_account_info(),
+            token_amount,
+        )?;
+        ctx.accounts.token_vault.reload()?;
+        let token_received = ctx.accounts.token_vault.amount;
+        // The curve holds at least the deposit, any virtual tokens beyond it keep the price
+        // finite once the deposit sells out
+        require!(virtual_token_reserve >= token_received, ErrorCode::InvalidLaunchPool);
+
+        let launch_pool = &mut ctx.accounts.launch_pool;
+        launch_pool.creator = ctx.accounts.creator.key();
+        launch_pool.token_mint = ctx.accounts.token_mint.key();
+        launch_pool.quote_mint = ctx.accounts.quote_mint.key();
+        launch_pool.token_vault = ctx.accounts.token_vault.key();
+        launch_pool.quote_vault = ctx.accounts.quote_vault.key();
+        launch_pool.token_supply = ctx.accounts.token_mint.supply;
+        launch_pool.virtual_token_reserve = virtual_token_reserve;
+        launch_pool.virtual_quote_reserve = virtual_quote_reserve;
+        launch_pool.token_reserve = token_received;
+        launch_pool.graduation_market_cap = graduation_market_cap;
+        launch_pool.fee_tier_bps = fee_tier_bps;
+        launch_pool.lp_disposition = lp_disposition;
+        launch_pool.pool = pool_key;
+        launch_pool.bump = *ctx.bumps.get("launch_pool").unwrap();
+        launch_pool.authority_bump = *ctx.bumps.get("launch_authority").unwrap();
+
+        // A threshold the curve already meets would graduate on the first buy, and one
+        // above what the deposit can reach would never graduate
+        let market_cap = launch_pool.market_cap().ok_or(ErrorCode::MathOverflow)?;
+        let max_market_cap = launch_pool.max_market_cap().ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            graduation_market_cap as u128 > market_cap
+                && graduation_market_cap as u128 <= max_market_cap,
+            ErrorCode::InvalidLaunchPool
+        );
+
+        emit!(LaunchPoolCreated {
+            launch_pool: launch_pool.key(),
+            creator: launch_pool.creator,
+            token_mint: launch_pool.token_mint,
+            quote_mint: launch_pool.quote_mint,
+            token_amount: token_received,
+            virtual_token_reserve,
+            virtual_quote_reserve,
+            graduation_market_cap,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_launch(
+        ctx: Context<TradeLaunchPool>,
+        quote_amount: u64,
+        min_tokens_out: u64,
+    ) -> Result<()> {
+        require!(quote_amount > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.launch_pool.complete, ErrorCode::LaunchPoolComplete);
+
+        // Transfer quote from user to the launch pool
+        let balance_before = ctx.accounts.quote_vault.amount;
+        transfer_from_user(
+            ctx.accounts.quote_token_program.to_account_info(),
+            ctx.accounts.user_quote_account.to_account_info(),
+            ctx.accounts.quote_vault.to_account_info(),
+            &ctx.accounts.quote_mint,
+            ctx.accounts.user.to_account_info(),
+            quote_amount,
+        )?;
+
+        // Transfer fees mean the pool can receive less than was sent
+        ctx.accounts.quote_vault.reload()?;
+        let quote_received = ctx.accounts.quote_vault.amount.checked_sub(balance_before).unwrap();
+
+        let launch_pool = &ctx.accounts.launch_pool;
+        let fee = (quote_received as u128)
+            .checked_mul(launch_pool.fee_tier_bps as u128)
+            .unwrap()
+            .div(FEE_TIER_DENOMINATOR as u128) as u64;
+        let quote_after_fee = quote_received.checked_sub(fee).unwrap();
+        let tokens_out = calculate_launch_output(
+            launch_pool.virtual_quote_reserve,
+            launch_pool.virtual_token_reserve,
+            quote_after_fee,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        require!(tokens_out > 0, ErrorCode::InvalidAmount);
+        require!(tokens_out <= launch_pool.token_reserve, ErrorCode::InsufficientLiquidity);
+
+        // Check slippage against what the user receives after any transfer fee
+        let tokens_sent = tokens_out
+            .checked_sub(get_transfer_fee(&ctx.accounts.token_mint, tokens_out)?)
+            .unwrap();
+        require!(tokens_sent >= min_tokens_out, ErrorCode::SlippageExceeded);
+
+        transfer_from_launch_pool(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.token_vault.to_account_info(),
+            ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_mint,
+            ctx.accounts.launch_authority.to_account_info(),
+            launch_pool,
+            tokens_out,
+        )?;
+
+        // The fee stays in the vault and is migrated with the raised quote, but does not
+        // move the curve
+        let launch_pool = &mut ctx.accounts.launch_pool;
+        launch_pool.virtual_quote_reserve =
+            launch_pool.virtual_quote_reserve.checked_add(quote_after_fee).unwrap();
+        launch_pool.virtual_token_reserve =
+            launch_pool.virtual_token_reserve.checked_sub(tokens_out).unwrap();
+        launch_pool.quote_reserve = launch_pool.quote_reserve.checked_add(quote_received).unwrap();
+        launch_pool.token_reserve = launch_pool.token_reserve.checked_sub(tokens_out).unwrap();
+
+        // The buy that reaches the threshold or sells out the deposit closes the curve
+        let market_cap = launch_pool.market_cap().ok_or(ErrorCode::MathOverflow)?;
+        if market_cap >= launch_pool.graduation_market_cap as u128
+            || launch_pool.token_reserve == 0
+        {
+            launch_pool.complete = true;
+        }
+
+        emit!(LaunchTradeExecuted {
+            launch_pool: launch_pool.key(),
+            user: ctx.accounts.user.key(),
+            is_buy: true,
+            quote_amount: quote_received,
+            token_amount: tokens_out,
+            fee,
+            virtual_quote_reserve: launch_pool.virtual_quote_reserve,
+            virtual_token_reserve: launch_pool.virtual_token_reserve,
+            complete: launch_pool.complete,
+        });
+
+        // The buy that closes the curve also migrates its reserves into the pool
+        if ctx.accounts.launch_pool.complete {
+            migrate_launch_pool(ctx.accounts)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn sell_launch(
+        ctx: Context<TradeLaunchPool>,
+        token_amount: u64,
+        min_quote_out: u64,
+    ) -> Result<()> {
+        require!(token_amount > 0, ErrorCode::InvalidAmount);
+        require!(!ctx.accounts.launch_pool.complete, ErrorCode::LaunchPoolComplete);
+
+        // Transfer tokens from user to the launch pool
+        let balance_before = ctx.accounts.token_vault.amount;
+        transfer_from_user(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.token_vault.to_account_info(),
+            &ctx.accounts.token_mint,
+            ctx.accounts.user.to_account_info(),
+            token_amount,
+        )?;
+
+        // Transfer fees mean the pool can receive less than was sent
+        ctx.accounts.token_vault.reload()?;
+        let tokens_received = ctx.accounts.token_vault.amount.checked_sub(balance_before).unwrap();
+
+        let launch_pool = &ctx.accounts.launch_pool;
+        let quote_out = calculate_launch_output(
+            launch_pool.virtual_token_reserve,
+            launch_pool.virtual_quote_reserve,
+            tokens_received,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+        let fee = (quote_out as u128)
+            .checked_mul(launch_pool.fee_tier_bps as u128)
+            .unwrap()
+            .div(FEE_TIER_DENOMINATOR as u128) as u64;
+        let quote_paid = quote_out.checked_sub(fee).unwrap();
+        require!(quote_paid > 0, ErrorCode::InvalidAmount);
+        // Only quote raised by buys can be paid out, the virtual reserve is never backed
+        require!(quote_paid <= launch_pool.quote_reserve, ErrorCode::InsufficientLiquidity);
+
+        // Check slippage against what the user receives after any transfer fee
+        let quote_sent = quote_paid
+            .checked_sub(get_transfer_fee(&ctx.accounts.quote_mint, quote_paid)?)
+            .unwrap();
+        require!(quote_sent >= min_quote_out, ErrorCode::SlippageExceeded);
+
+        transfer_from_launch_pool(
+            ctx.accounts.quote_token_program.to_account_info(),
+            ctx.accounts.quote_vault.to_account_info(),
+            ctx.accounts.user_quote_account.to_account_info(),
+            &ctx.accounts.quote_mint,
+            ctx.accounts.launch_authority.to_account_info(),
+            launch_pool,
+            quote_paid,
+        )?;
+
+        let launch_pool = &mut ctx.accounts.launch_pool;
+        launch_pool.virtual_token_reserve =
+            launch_pool.virtual_token_reserve.checked_add(tokens_received).unwrap();
+        launch_pool.virtual_quote_reserve =
+            launch_pool.virtual_quote_reserve.checked_sub(quote_out).unwrap();
+        launch_pool.token_reserve = launch_pool.token_reserve.checked_add(tokens_received).unwrap();
+        launch_pool.quote_reserve = launch_pool.quote_reserve.checked_sub(quote_paid).unwrap();
+
+        emit!(LaunchTradeExecuted {
+            launch_pool: launch_pool.key(),
+            user: ctx.accounts.user.key(),
+            is_buy: false,
+            quote_amount: quote_paid,
+            token_amount: tokens_received,
+            fee,
+            virtual_quote_reserve: launch_pool.virtual_quote_reserve,
+            virtual_token_reserve: launch_pool.virtual_token_reserve,
+            complete: launch_pool.complete,
+        });
+
+        Ok(())
+    }
+
+}
+
+// Populates the fields shared by every pool type
+fn write_pool_state(
+    accounts: &mut InitializePool,
+    bump: u8,
+    authority_bump: u8,
+    fee_tier_bps: u16,
+    pool_type: PoolType,
+) -> Result<()> {
+    // Pools can only be created at a fee tier enabled by the protocol
+    require!(
+        accounts.fee_tier_registry.fee_tiers.contains(&fee_tier_bps),
+        ErrorCode::InvalidFeeTier
+    );
+    // Mints are ordered by pubkey so each pair maps to a single pool per fee tier
+    require!(
+        accounts.token_a_mint.key() < accounts.token_b_mint.key(),
+        ErrorCode::InvalidMintOrder
+    );
+    validate_mint_extensions(&accounts.token_a_mint)?;
+    validate_mint_extensions(&accounts.token_b_mint)?;
+
+    let pool = &mut accounts.pool;
+    pool.token_a_mint = accounts.token_a_mint.key();
+    pool.token_b_mint = accounts.token_b_mint.key();
+    pool.token_a_account = accounts.token_a_account.key();
+    pool.token_b_account = accounts.token_b_account.key();
+    pool.lp_mint = accounts.lp_mint.key();
+    pool.authority = accounts.authority.key();
+    pool.fee_numerator = fee_tier_bps as u64;
+    pool.fee_denominator = FEE_TIER_DENOMINATOR;
+    pool.fee_tier_bps = fee_tier_bps;
+    pool.pool_type = pool_type;
+    pool.last_observation_ts = Clock::get()?.unix_timestamp;
+    pool.bump = bump;
+    pool.authority_bump = authority_bump;
+
+    emit!(PoolInitialized {
+        pool: pool.key(),
+        token_a_mint: pool.token_a_mint,
+        token_b_mint: pool.token_b_mint,
+        lp_mint: pool.lp_mint,
+        authority: pool.authority,
+        pool_type,
+        fee_tier_bps,
+    });
+
+    // Append the pool to the current registry page so clients can enumerate pools
+    let pool_key = accounts.pool.key();
+    let pool_registry = &mut accounts.pool_registry;
+    let pool_registry_page = &mut accounts.pool_registry_page;
+    require!(
+        pool_registry_page.index as u64
+            == pool_registry.pool_count / POOLS_PER_REGISTRY_PAGE as u64,
+        ErrorCode::InvalidRegistryPage
+    );
+    pool_registry_page.pools.push(pool_key);
+    pool_registry.pool_count = pool_registry.pool_count.checked_add(1).unwrap();
+
+    Ok(())
+}
+
+// Rejects Token-2022 mints with extensions the pool cannot honour, such as transfer
+// hooks that need extra accounts or permanent delegates that could move vault funds
+fn validate_mint_extensions(mint: &InterfaceAccount<Mint>) -> Result<()> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() {
+        return Ok(());
+    }
+
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    for extension in mint_state.get_extension_types()? {
+        require!(
+            matches!(
+                extension,
+                ExtensionType::TransferFeeConfig
+                    | ExtensionType::MetadataPointer
+                    | ExtensionType::TokenMetadata
+                    | ExtensionType::InterestBearingConfig
+            ),
+            ErrorCode::UnsupportedMintExtension
+        );
+    }
+
+    Ok(())
+}
+
+// Fee withheld by the Token-2022 transfer fee extension when `amount` is transferred
+pub fn get_transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() {
+        return Ok(0);
+    }
+
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let fee = match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(transfer_fee_config) => transfer_fee_config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(ErrorCode::MathOverflow)?,
+        Err(_) => 0,
+    };
+
+    Ok(fee)
+}
+
+// Fee to add on top of `post_fee_amount` so that the full amount arrives
+pub fn get_transfer_inverse_fee(
+    mint: &InterfaceAccount<Mint>,
+    post_fee_amount: u64,
+) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() || post_fee_amount == 0 {
+        return Ok(0);
+    }
+
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let fee = match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(transfer_fee_config) => transfer_fee_config
+            .calculate_inverse_epoch_fee(Clock::get()?.epoch, post_fee_amount)
+            .ok_or(ErrorCode::MathOverflow)?,
+        Err(_) => 0,
+    };
+
+    Ok(fee)
+}
+
+// Transfers tokens out of an account owned by the signing user
+fn transfer_from_user<'info>(
+    token_program: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    authority: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program,
+            TransferChecked {
+                from,
+                mint: mint.to_account_info(),
+                to,
+                authority,
+            },
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// Transfers tokens out of a pool vault, signed by the pool authority
+fn transfer_from_pool<'info>(
+    token_program: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    pool_authority: AccountInfo<'info>,
+    pool: Pubkey,
+    authority_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program,
+            TransferChecked {
+                from,
+                mint: mint.to_account_info(),
+                to,
+                authority: pool_authority,
+            },
+            &[&[b"pool_authority", pool.as_ref(), &[authority_bump]][..]],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// Transfers tokens out of a multi-asset pool vault, signed by that pool's authority
+fn transfer_from_multi_pool<'info>(
+    token_program: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    pool_authority: AccountInfo<'info>,
+    pool: Pubkey,
+    authority_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program,
+            TransferChecked {
+                from,
+                mint: mint.to_account_info(),
+                to,
+                authority: pool_authority,
+            },
+            &[&[b"pool_authority", pool.as_ref(), &[authority_bump]][..]],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// Mints LP tokens of a multi-asset pool, signed by that pool's authority
+fn mint_multi_pool_lp<'info>(
+    token_program: AccountInfo<'info>,
+    lp_mint: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    pool_authority: AccountInfo<'info>,
+    pool: Pubkey,
+    authority_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    token_interface::mint_to(
+        CpiContext::new_with_signer(
+            token_program,
+            token_interface::MintTo {
+                mint: lp_mint,
+                to,
+                authority: pool_authority,
+            },
+            &[&[b"pool_authority", pool.as_ref(), &[authority_bump]][..]],
+        ),
+        amount,
+    )
+}
+
+// Transfers tokens out of a launch pool vault, signed by the launch authority
+fn transfer_from_launch_pool<'info>(
+    token_program: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    launch_authority: AccountInfo<'info>,
+    launch_pool: &Account<'info, LaunchPool>,
+    amount: u64,
+) -> Result<()> {
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program,
+            TransferChecked {
+                from,
+                mint: mint.to_account_info(),
+                to,
+                authority: launch_authority,
+            },
+            &[&[
+                b"launch_authority",
+                launch_pool.key().as_ref(),
+                &[launch_pool.authority_bump],
+            ][..]],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// Moves a launch pool's reserves into its constant product pool once the curve closes.
+// The pool is seeded at the curve's final price so trading continues where the curve
+// stopped, and the unsold tokens that would push the price lower are burned
+fn migrate_launch_pool(accounts: &mut TradeLaunchPool) -> Result<()> {
+    let launch_pool = &accounts.launch_pool;
+    let quote_amount = launch_pool.quote_reserve;
+    let token_amount = ((quote_amount as u128)
+        .checked_mul(launch_pool.virtual_token_reserve as u128)
+        .unwrap()
+        .div(launch_pool.virtual_quote_reserve as u128) as u64)
+        .min(launch_pool.token_reserve);
+    let tokens_burned = launch_pool.token_reserve.checked_sub(token_amount).unwrap();
+
+    // The pool vaults are public, so only what this migration sends in seeds the pool
+    let token_balance_before = accounts.pool_token_vault.amount;
+    let quote_balance_before = accounts.pool_quote_vault.amount;
+    transfer_from_launch_pool(
+        accounts.token_program.to_account_info(),
+        accounts.token_vault.to_account_info(),
+        accounts.pool_token_vault.to_account_info(),
+        &accounts.token_mint,
+        accounts.launch_authority.to_account_info(),
+        launch_pool,
+        token_amount,
+    )?;
+    transfer_from_launch_pool(
+        accounts.quote_token_program.to_account_info(),
+        accounts.quote_vault.to_account_info(),
+        accounts.pool_quote_vault.to_account_info(),
+        &accounts.quote_mint,
+        accounts.launch_authority.to_account_info(),
+        launch_pool,
+        quote_amount,
+    )?;
+    if tokens_burned > 0 {
+        token_interface::burn(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                token_interface::Burn {
+                    mint: accounts.token_mint.to_account_info(),
+                    from: accounts.token_vault.to_account_info(),
+                    authority: accounts.launch_authority.to_account_info(),
+                },
+                &[&[
+                    b"launch_authority",
+                    launch_pool.key().as_ref(),
+                    &[launch_pool.authority_bump],
+                ][..]],
+            ),
+            tokens_burned,
+        )?;
+    }
+
+    // Transfer fees mean the pool can receive less than was sent
+    accounts.pool_token_vault.reload()?;
+    accounts.pool_quote_vault.reload()?;
+    let token_received =
+        accounts.pool_token_vault.amount.checked_sub(token_balance_before).unwrap();
+    let quote_received =
+        accounts.pool_quote_vault.amount.checked_sub(quote_balance_before).unwrap();
+    let lp_tokens = calculate_constant_product_lp_tokens(0, 0, token_received, quote_received, 0)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(lp_tokens > MINIMUM_LIQUIDITY, ErrorCode::InitialDepositTooSmall);
+
+    // Every LP token goes to the pool's locked account, burned LP keeps only the
+    // minimum every pool locks
+    let pool = &accounts.pool;
+    token_interface::mint_to(
+        CpiContext::new_with_signer(
+            accounts.lp_token_program.to_account_info(),
+            token_interface::MintTo {
+                mint: accounts.lp_mint.to_account_info(),
+                to: accounts.locked_lp_token.to_account_info(),
+                authority: accounts.pool_authority.to_account_info(),
+            },
+            &[&[b"pool_authority", pool.key().as_ref(), &[pool.authority_bump]][..]],
+        ),
+        lp_tokens,
+    )?;
+    let lp_burned = match accounts.launch_pool.lp_disposition {
+        LpDisposition::Burn => lp_tokens - MINIMUM_LIQUIDITY,
+        LpDisposition::Lock => 0,
+    };
+    if lp_burned > 0 {
+        token_interface::burn(
+            CpiContext::new_with_signer(
+                accounts.lp_token_program.to_account_info(),
+                token_interface::Burn {
+                    mint: accounts.lp_mint.to_account_info(),
+                    from: accounts.locked_lp_token.to_account_info(),
+                    authority: accounts.pool_authority.to_account_info(),
+                },
+                &[&[b"pool_authority", pool.key().as_ref(), &[pool.authority_bump]][..]],
+            ),
+            lp_burned,
+        )?;
+    }
+
+    // The pool was created paused so nobody could set its price before the migration
+    let pool = &mut accounts.pool;
+    if pool.token_a_mint == accounts.launch_pool.token_mint {
+        pool.reserve_a = token_received;
+        pool.reserve_b = quote_received;
+    } else {
+        pool.reserve_a = quote_received;
+        pool.reserve_b = token_received;
+    }
+    pool.paused = false;
+
+    let launch_pool = &mut accounts.launch_pool;
+    launch_pool.token_reserve = 0;
+    launch_pool.quote_reserve = 0;
+
+    emit!(LaunchPoolGraduated {
+        launch_pool: launch_pool.key(),
+        pool: launch_pool.pool,
+        token_amount,
+        quote_amount,
+        tokens_burned,
+        lp_tokens,
+        lp_burned,
+    });
+
+    Ok(())
+}
+
+// Opens the user's temporary wSOL account holding `lamports` of native SOL,
+// which stands in for a missing user token account on the native side of a pool
+fn wrap_sol<'info>(
+    token_program: AccountInfo<'info>,
+    system_program: Option<&Program<'info, System>>,
+    wsol_account: Option<&UncheckedAccount<'info>>,
+    wsol_bump: Option<&u8>,
+    mint: &InterfaceAccount<'info, Mint>,
+    user: AccountInfo<'info>,
+    lamports: u64,
+) -> Result<AccountInfo<'info>> {
+    // Only the native side of a pool may be paid in lamports
+    require!(mint.key() == native_mint::ID, ErrorCode::MissingTokenAccount);
+    let (system_program, wsol_account, wsol_bump) =
+        match (system_program, wsol_account, wsol_bump) {
+            (Some(system_program), Some(wsol_account), Some(wsol_bump)) => {
+                (system_program, wsol_account, *wsol_bump)
+            }
+            _ => return err!(ErrorCode::MissingNativeSolAccounts),
+        };
+
+    // Native token accounts take their balance from the lamports above the rent exemption
+    let rent = Rent::get()?.minimum_balance(TOKEN_ACCOUNT_LEN as usize);
+    let required_lamports = rent.checked_add(lamports).ok_or(ErrorCode::MathOverflow)?;
+    let signer_seeds: &[&[&[u8]]] = &[&[b"wsol", user.key.as_ref(), &[wsol_bump]]];
+    let current_lamports = wsol_account.lamports();
+    if current_lamports == 0 {
+        anchor_lang::system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                anchor_lang::system_program::CreateAccount {
+                    from: user.clone(),
+                    to: wsol_account.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            required_lamports,
+            TOKEN_ACCOUNT_LEN,
+            token_program.key,
+        )?;
+    } else {
+        // Anyone can send lamports to the address first, which would make
+        // create_account fail, so top it up and claim it the way Anchor's init does
+        let shortfall = required_lamports.saturating_sub(current_lamports);
+        if shortfall > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: user.clone(),
+                        to: wsol_account.to_account_info(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        anchor_lang::system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Allocate {
+                    account_to_allocate: wsol_account.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            TOKEN_ACCOUNT_LEN,
+        )?;
+        anchor_lang::system_program::assign(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Assign {
+                    account_to_assign: wsol_account.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            token_program.key,
+        )?;
+    }
+    token_interface::initialize_account3(CpiContext::new(
+        token_program,
+        token_interface::InitializeAccount3 {
+            account: wsol_account.to_account_info(),
+            mint: mint.to_account_info(),
+            authority: user,
+        },
+    ))?;
+
+    Ok(wsol_account.to_account_info())
+}
+
+// Closes the user's temporary wSOL account, returning its balance and rent as lamports
+fn unwrap_sol<'info>(
+    token_program: AccountInfo<'info>,
+    wsol_account: AccountInfo<'info>,
+    user: AccountInfo<'info>,
+) -> Result<()> {
+    token_interface::close_account(CpiContext::new(
+        token_program,
+        token_interface::CloseAccount {
+            account: wsol_account,
+            destination: user.clone(),
+            authority: user,
+        },
+    ))
+}
+
+// Swap direction from the user's token accounts, a missing account standing for native SOL
+fn swap_mints(
+    pool: &Pool,
+    user_token_in: Option<&InterfaceAccount<TokenAccount>>,
+    user_token_out: Option<&InterfaceAccount<TokenAccount>>,
+) -> Result<(Pubkey, Pubkey)> {
+    let other_mint = |mint: Pubkey| {
+        if mint == pool.token_a_mint {
+            pool.token_b_mint
+        } else {
+            pool.token_a_mint
+        }
+    };
+    let (mint_in, mint_out) = match (user_token_in, user_token_out) {
+        (Some(user_token_in), Some(user_token_out)) => (user_token_in.mint, user_token_out.mint),
+        (Some(user_token_in), None) => (user_token_in.mint, other_mint(user_token_in.mint)),
+        (None, Some(user_token_out)) => (other_mint(user_token_out.mint), user_token_out.mint),
+        (None, None) => return err!(ErrorCode::MissingTokenAccount),
+    };
+    require!(mint_in != mint_out, ErrorCode::InvalidRoute);
+
+    Ok((mint_in, mint_out))
+}
+
+// Pays the referrer's share of a swap fee out of the input vault and records it in their stats
+fn pay_referral<'info>(
+    pool: &Account<'info, Pool>,
+    token_program: AccountInfo<'info>,
+    vault: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    pool_authority: AccountInfo<'info>,
+    user: Pubkey,
+    referrer_token: Option<&InterfaceAccount<'info, TokenAccount>>,
+    referrer_stats: Option<&mut Account<'info, ReferrerStats>>,
+    fee_config: Option<&Account<'info, FeeConfig>>,
+    amount_in: u64,
+    fee: u64,
+) -> Result<u64> {
+    let referrer_token = match referrer_token {
+        Some(referrer_token) => referrer_token,
+        None => return Ok(0),
+    };
+    // Concentrated pools credit the whole fee to in-range liquidity as they swap
+    require!(pool.pool_type != PoolType::Concentrated, ErrorCode::InvalidPoolType);
+    let referrer_stats = referrer_stats.ok_or(ErrorCode::InvalidReferrer)?;
+    let fee_config = fee_config.ok_or(ErrorCode::InvalidReferrer)?;
+    require!(
+        referrer_token.mint == mint.key()
+            && referrer_stats.referrer == referrer_token.owner
+            && referrer_stats.mint == mint.key(),
+        ErrorCode::InvalidReferrer
+    );
+    // Swappers may not refer themselves to claw back part of their own fee
+    require!(referrer_token.owner != user, ErrorCode::SelfReferral);
+
+    let referral_fee = (fee as u128)
+        .checked_mul(fee_config.referral_share_bps as u128)
+        .unwrap()
+        .div(FEE_TIER_DENOMINATOR as u128) as u64;
+    if referral_fee > 0 {
+        transfer_from_pool(
+            token_program,
+            vault,
+            referrer_token.to_account_info(),
+            mint,
+            pool_authority,
+            pool.key(),
+            pool.authority_bump,
+            referral_fee,
+        )?;
+    }
+
+    referrer_stats.swap_count = referrer_stats.swap_count.checked_add(1).unwrap();
+    referrer_stats.volume = referrer_stats.volume.checked_add(amount_in as u128).unwrap();
+    referrer_stats.fees_earned =
+        referrer_stats.fees_earned.checked_add(referral_fee as u128).unwrap();
+
+    Ok(referral_fee)
+}
+
+// Oracle price of token A in token B in raw token units as Q64.64, None when the oracle
+// is stale, unreadable or not confident enough
+fn oracle_price_x64(
+    oracle_config: &OracleConfig,
+    price_feed: &AccountInfo,
+    decimals_a: u8,
+    decimals_b: u8,
+    now: i64,
+) -> Option<u128> {
+    let price_data = openfund_oracle::read_price(oracle_config, price_feed, now).ok()?;
+    if price_data.price <= 0 {
+        return None;
+    }
+
+    // price * 10^exponent whole B per whole A, rescaled by the difference in decimals
+    let scale = price_data.exponent + decimals_b as i32 - decimals_a as i32;
+    let price_x64 = U256::from(price_data.price as u64) << 64;
+    let price_x64 = if scale >= 0 {
+        price_x64.checked_mul(U256::from(10u64).checked_pow(U256::from(scale))?)?
+    } else {
+        price_x64 / U256::from(10u64).checked_pow(U256::from(-scale))?
+    };
+    u128::try_from(price_x64).ok().filter(|price| *price > 0)
+}
+
+// Rejects a swap that leaves the pool price further from its oracle than allowed, unless it
+// moves the price back towards the oracle
+fn check_oracle_guard(
+    pool: &Pool,
+    oracle_config: Option<&Account<OracleConfig>>,
+    price_feed: Option<&UncheckedAccount>,
+    decimals: (u8, u8),
+    price_before_x64: Option<u128>,
+    price_after_x64: Option<u128>,
+    now: i64,
+) -> Result<()> {
+    // PMM pools may link an oracle only to price around it, without a guard
+    if pool.oracle_config == Pubkey::default() || pool.max_oracle_deviation_bps == 0 {
+        return Ok(());
+    }
+    let (oracle_config, price_feed) = match (oracle_config, price_feed) {
+        (Some(oracle_config), Some(price_feed)) => (oracle_config, price_feed),
+        _ => return err!(ErrorCode::InvalidOracle),
+    };
+    require!(
+        oracle_config.key() == pool.oracle_config && price_feed.key() == oracle_config.price_feed,
+        ErrorCode::InvalidOracle
+    );
+
+    let oracle_price_x64 =
+        match oracle_price_x64(oracle_config, price_feed, decimals.0, decimals.1, now) {
+            Some(oracle_price_x64) => oracle_price_x64,
+            None if pool.oracle_stale_policy == OracleStalePolicy::FailOpen => return Ok(()),
+            None => return err!(ErrorCode::OracleStale),
+        };
+    let deviation_bps = |price_x64: Option<u128>| {
+        price_x64.map_or(0, |price_x64| {
+            let deviation = U256::from(price_x64.abs_diff(oracle_price_x64))
+                * U256::from(FEE_TIER_DENOMINATOR)
+                / U256::from(oracle_price_x64);
+            u64::try_from(deviation).unwrap_or(u64::MAX)
+        })
+    };
+    let deviation_after = deviation_bps(price_after_x64);
+    require!(
+        deviation_after <= pool.max_oracle_deviation_bps as u64
+            || deviation_after < deviation_bps(price_before_x64),
+        ErrorCode::OraclePriceDeviation
+    );
+
+    Ok(())
+}
+
+// Reads the oracle a PMM pool prices the swap around. A stale, low-confidence or unreadable
+// price is stored as zero so the swap falls back to pricing on the reserves alone
+fn refresh_pmm_price(
+    pool: &mut Pool,
+    oracle_config: Option<&Account<OracleConfig>>,
+    price_feed: Option<&UncheckedAccount>,
+    decimals: (u8, u8),
+    now: i64,
+) -> Result<()> {
+    if pool.pool_type != PoolType::Pmm {
+        return Ok(());
+    }
+    if pool.oracle_config == Pubkey::default() {
+        pool.pmm_price_x64 = 0;
+        return Ok(());
+    }
+    let (oracle_config, price_feed) = match (oracle_config, price_feed) {
+        (Some(oracle_config), Some(price_feed)) => (oracle_config, price_feed),
+        _ => return err!(ErrorCode::InvalidOracle),
+    };
+    require!(
+        oracle_config.key() == pool.oracle_config && price_feed.key() == oracle_config.price_feed,
+        ErrorCode::InvalidOracle
+    );
+
+    pool.pmm_price_x64 =
+        oracle_price_x64(oracle_config, price_feed, decimals.0, decimals.1, now).unwrap_or(0);
+
+    Ok(())
+}
+
+// Marginal prices of token A in token B and of token B in token A, as Q64.64
+pub fn spot_prices_x64(
+    pool: &Pool,
+    reserve_a: u64,
+    reserve_b: u64,
+    now: i64,
+) -> Option<(u128, u128)> {
+    let q64 = U256::one() << 64;
+    let (price_a, price_b) = match pool.pool_type {
+        PoolType::ConstantProduct => {
+            if reserve_a == 0 || reserve_b == 0 {
+                return None;
+            }
+            (
+                (U256::from(reserve_b) << 64) / U256::from(reserve_a),
+                (U256::from(reserve_a) << 64) / U256::from(reserve_b),
+            )
+        }
+        PoolType::StableSwap => {
+            if reserve_a == 0 || reserve_b == 0 {
+                return None;
+            }
+            // Ratio of the invariant's partial derivatives:
+            // dF/dx = Ann + D^3 / (4 * x^2 * y), dF/dy = Ann + D^3 / (4 * x * y^2)
+            let amp = pool.current_amp(now);
+            let d = U256::from(compute_stable_d(amp, reserve_a, reserve_b)?);
+            let x = U256::from(reserve_a);
+            let y = U256::from(reserve_b);
+            let two = U256::from(2u8);
+            let ann_x64 = U256::from(amp).checked_mul(U256::from(STABLE_N_COINS))? << 64;
+            let d_over_2x_x64 = (d << 64).checked_div(x.checked_mul(two)?)?;
+            let d_over_2y_x64 = (d << 64).checked_div(y.checked_mul(two)?)?;
+            let derivative_x = ann_x64.checked_add(
+                d_over_2x_x64
+                    .checked_mul(d)?
+                    .checked_div(x.checked_mul(two)?)?
+                    .checked_mul(d)?
+                    .checked_div(y)?,
+            )?;
+            let derivative_y = ann_x64.checked_add(
+                d_over_2y_x64
+                    .checked_mul(d)?
+                    .checked_div(y.checked_mul(two)?)?
+                    .checked_mul(d)?
+                    .checked_div(x)?,
+            )?;
+            (
+                derivative_x.checked_mul(q64)?.checked_div(derivative_y)?,
+                derivative_y.checked_mul(q64)?.checked_div(derivative_x)?,
+            )
+        }
+        PoolType::Weighted => {
+            if reserve_a == 0 || reserve_b == 0 {
+                return None;
+            }
+            // (reserve_b / weight_b) / (reserve_a / weight_a)
+            let (weight_a, weight_b) = pool.current_weights(now);
+            let value_a = U256::from(reserve_a).checked_mul(U256::from(weight_b))?;
+            let value_b = U256::from(reserve_b).checked_mul(U256::from(weight_a))?;
+            (
+                (value_b << 64).checked_div(value_a)?,
+                (value_a << 64).checked_div(value_b)?,
+            )
+        }
+        PoolType::Pmm => {
+            if reserve_a == 0 || reserve_b == 0 {
+                return None;
+            }
+            let (virtual_a, virtual_b) = pmm_virtual_reserves(pool, reserve_a, reserve_b)?;
+            (
+                (virtual_b << 64).checked_div(virtual_a)?,
+                (virtual_a << 64).checked_div(virtual_b)?,
+            )
+        }
+        PoolType::Concentrated => {
+            let price_x128 =
+                U256::from(pool.sqrt_price_x64).checked_mul(U256::from(pool.sqrt_price_x64))?;
+            (price_x128 >> 64, (U256::one() << 192).checked_div(price_x128)?)
+        }
+    };
+
+    // Extreme prices saturate rather than wrapping the accumulators
+    let clamp = |price: U256| u128::try_from(price).unwrap_or(u128::MAX);
+    Some((clamp(price_a), clamp(price_b)))
+}
+
+// Price accumulators extended from the last update to `now` at the current spot price
+pub fn cumulative_prices_at(
+    pool: &Pool,
+    reserve_a: u64,
+    reserve_b: u64,
+    now: i64,
+) -> Option<(u128, u128)> {
+    let elapsed = now.checked_sub(pool.last_observation_ts)?;
+    if elapsed <= 0 {
+        return Some((pool.price_a_cumulative_x64, pool.price_b_cumulative_x64));
+    }
+
+    // An empty pool has no price and contributes nothing
+    match spot_prices_x64(pool, reserve_a, reserve_b, now) {
+        Some((price_a, price_b)) => Some((
+            pool.price_a_cumulative_x64.wrapping_add(price_a.wrapping_mul(elapsed as u128)),
+            pool.price_b_cumulative_x64.wrapping_add(price_b.wrapping_mul(elapsed as u128)),
+        )),
+        None => Some((pool.price_a_cumulative_x64, pool.price_b_cumulative_x64)),
+    }
+}
+
+pub fn update_price_accumulators(
+    pool: &mut Pool,
+    reserve_a: u64,
+    reserve_b: u64,
+    now: i64,
+) -> Option<()> {
+    if now <= pool.last_observation_ts {
+        return Some(());
+    }
+
+    let (price_a_cumulative, price_b_cumulative) =
+        cumulative_prices_at(pool, reserve_a, reserve_b, now)?;
+    pool.price_a_cumulative_x64 = price_a_cumulative;
+    pool.price_b_cumulative_x64 = price_b_cumulative;
+    pool.last_observation_ts = now;
+
+    Some(())
+}
+
+// Updates the accumulators from the tracked reserves and writes an observation when a buffer
+// is supplied
+fn record_price(
+    pool: &mut Pool,
+    observation_buffer: Option<&mut Account<ObservationBuffer>>,
+    now: i64,
+) -> Result<()> {
+    let (reserve_a, reserve_b) = (pool.reserve_a, pool.reserve_b);
+    update_price_accumulators(pool, reserve_a, reserve_b, now).ok_or(ErrorCode::MathOverflow)?;
+
+    if let Some(observation_buffer) = observation_buffer {
+        observation_buffer.record(
+            pool.last_observation_ts,
+            pool.price_a_cumulative_x64,
+            pool.price_b_cumulative_x64,
+        );
+    }
+
+    Ok(())
+}
+
+// Flash loan fee on `amount`, charged at the pool's swap fee and rounded up
+pub fn calculate_flash_fee(pool: &Pool, amount: u64) -> Option<u64> {
+    let fee = (amount as u128)
+        .checked_mul(pool.fee_numerator as u128)?
+        .checked_add(pool.fee_denominator as u128 - 1)?
+        .checked_div(pool.fee_denominator as u128)?;
+    u64::try_from(fee).ok()
+}
+
+// LP tokens minted for a two-sided deposit into a constant product, stable or weighted pool
+pub fn calculate_lp_tokens(
+    pool: &Pool,
+    reserve_a: u64,
+    reserve_b: u64,
+    amount_a: u64,
+    amount_b: u64,
+    lp_supply: u64,
+    now: i64,
+) -> Option<u64> {
+    if pool.pool_type == PoolType::StableSwap {
+        calculate_stable_lp_tokens(
+            pool,
+            pool.swap_fee_numerator(now),
+            pool.current_amp(now),
+            reserve_a,
+            reserve_b,
+            amount_a,
+            amount_b,
+            lp_supply,
+        )
+    } else if pool.pool_type == PoolType::Weighted && lp_supply == 0 {
+        // The first deposit mints the weighted geometric mean of the balances, which
+        // matches the constant product sqrt(a * b) at 50/50. Later deposits are priced
+        // by their share of the reserves like any other proportional deposit
+        let (weight_a, weight_b) = pool.current_weights(now);
+        calculate_weighted_invariant(amount_a, amount_b, weight_a, weight_b)
+    } else {
+        calculate_constant_product_lp_tokens(reserve_a, reserve_b, amount_a, amount_b, lp_supply)
+    }
+}
+
+// LP tokens and reserves resulting from a two-sided deposit
+pub struct DepositOutcome {
+    pub lp_tokens: u64, // LP tokens minted to the depositor
+    pub locked_lp: u64, // LP tokens locked forever, MINIMUM_LIQUIDITY on the first deposit
+    pub reserve_a: u64, // Token A reserve after the deposit
+    pub reserve_b: u64, // Token B reserve after the deposit
+}
+
+// Applies a two-sided deposit of what the vaults actually received. Pricing reads the
+// pool's tracked reserves, never the vault balances, so donations cannot move it
+pub fn apply_deposit(
+    pool: &Pool,
+    amount_a: u64,
+    amount_b: u64,
+    lp_supply: u64,
+    now: i64,
+) -> Result<DepositOutcome> {
+    let lp_tokens = calculate_lp_tokens(
+        pool,
+        pool.reserve_a,
+        pool.reserve_b,
+        amount_a,
+        amount_b,
+        lp_supply,
+        now,
+    )
+    .ok_or(ErrorCode::MathOverflow)?;
+
+    // The first deposit sets the price of an LP token, so it must be large enough that
+    // the locked minimum makes inflating that price unprofitable
+    let locked_lp = if lp_supply == 0 {
+        require!(
+            amount_a >= pool.min_initial_deposit_a && amount_b >= pool.min_initial_deposit_b,
+            ErrorCode::InitialDepositTooSmall
+        );
+        require!(lp_tokens > MINIMUM_LIQUIDITY, ErrorCode::InitialDepositTooSmall);
+        MINIMUM_LIQUIDITY
+    } else {
+        0
+    };
+
+    Ok(DepositOutcome {
+        lp_tokens: lp_tokens - locked_lp,
+        locked_lp,
+        reserve_a: pool.reserve_a.checked_add(amount_a).ok_or(ErrorCode::MathOverflow)?,
+        reserve_b: pool.reserve_b.checked_add(amount_b).ok_or(ErrorCode::MathOverflow)?,
+    })
+}
+
+// Output of a trade against a launch pool's virtual reserves (x * y = k). The new output
+// reserve is rounded up so rounding never lets a buy and sell round trip drain the pool
+pub fn calculate_launch_output(reserve_in: u64, reserve_out: u64, amount_in: u64) -> Option<u64> {
+    let new_reserve_in = (reserve_in as u128).checked_add(amount_in as u128)?;
+    let product = (reserve_in as u128).checked_mul(reserve_out as u128)?;
+    let new_reserve_out = product
+        .checked_add(new_reserve_in.checked_sub(1)?)?
+        .checked_div(new_reserve_in)?;
+    u64::try_from((reserve_out as u128).checked_sub(new_reserve_out)?).ok()
+}
+
+// LP tokens for a constant product deposit, including the locked minimum on the first one
+pub fn calculate_constant_product_lp_tokens(
+    reserve_a: u64,
+    reserve_b: u64,
+    amount_a: u64,
+    amount_b: u64,
+    lp_supply: u64,
+) -> Option<u64> {
+    if lp_supply == 0 {
+        // Initial liquidity - Use square root of product
+        let liquidity = integer_sqrt((amount_a as u128).checked_mul(amount_b as u128)?);
+        u64::try_from(liquidity).ok()
+    } else {
+        // Calculate based on the ratio of existing reserves
+        let lp_amount_a = (amount_a as u128)
+            .checked_mul(lp_supply as u128)?
+            .checked_div(reserve_a as u128)?;
+        let lp_amount_b = (amount_b as u128)
+            .checked_mul(lp_supply as u128)?
+            .checked_div(reserve_b as u128)?;
+
+        // Use the minimum to prevent manipulation
+        u64::try_from(std::cmp::min(lp_amount_a, lp_amount_b)).ok()
+    }
+}
+
+// Floor of the square root, by Newton's method so the result never depends on float rounding
+pub fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+
+    // Start above the root, each step then decreases until it reaches the floor
+    let mut x = 1u128 << ((128 - value.leading_zeros() + 1) / 2);
+    loop {
+        let y = (x + value / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+// Shortfall of `amount_out` against `amount_in` converted at the spot price, in bps
+pub fn price_impact_bps(price_in_x64: u128, amount_in: u64, amount_out: u64) -> Option<u64> {
+    let expected_out = U256::from(price_in_x64).checked_mul(U256::from(amount_in))? >> 64;
+    if expected_out.is_zero() || U256::from(amount_out) >= expected_out {
+        return Some(0);
+    }
+    let impact = (expected_out - U256::from(amount_out))
+        .checked_mul(U256::from(10_000u64))?
+        / expected_out;
+    Some(impact.as_u64())
+}
+
+// Relative move of token A's spot price between two sets of reserves, in bps
+pub fn price_change_bps(
+    pool: &Pool,
+    reserves_before: (u64, u64),
+    reserves_after: (u64, u64),
+    now: i64,
+) -> Option<u64> {
+    let prices_before = spot_prices_x64(pool, reserves_before.0, reserves_before.1, now);
+    let prices_after = spot_prices_x64(pool, reserves_after.0, reserves_after.1, now);
+    match (prices_before, prices_after) {
+        (Some((price_before_x64, _)), Some((price_after_x64, _))) if price_before_x64 > 0 => {
+            relative_change_bps(price_before_x64, price_after_x64)
+        }
+        // Empty pools have no price to move
+        _ => Some(0),
+    }
+}
+
+// Relative move from `from_x64` to `to_x64` in bps, saturating at u64::MAX
+pub fn relative_change_bps(from_x64: u128, to_x64: u128) -> Option<u64> {
+    let change = U256::from(from_x64.abs_diff(to_x64))
+        .checked_mul(U256::from(10_000u64))?
+        .checked_div(U256::from(from_x64))?;
+    Some(u64::try_from(change).unwrap_or(u64::MAX))
+}
+
+// Amount of the output token released for `amount_in_after_fee` of the input token
+pub fn calculate_swap_output(
+    pool: &Pool,
+    a_to_b: bool,
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in_after_fee: u64,
+    now: i64,
+) -> Option<u64> {
+    match pool.pool_type {
+        PoolType::ConstantProduct => {
+            // Constant product formula: (x * y = k)
+            // new_reserve_out = (reserve_in * reserve_out) / (reserve_in + amount_in_after_fee)
+            // amount_out = reserve_out - new_reserve_out
+            let new_reserve_in = reserve_in.checked_add(amount_in_after_fee)?;
+            let product = (reserve_in as u128).checked_mul(reserve_out as u128)?;
+            let new_reserve_out = product.checked_div(new_reserve_in as u128)? as u64;
+            reserve_out.checked_sub(new_reserve_out)
+        }
+        PoolType::StableSwap => {
+            let amp = pool.current_amp(now);
+            let d = compute_stable_d(amp, reserve_in, reserve_out)?;
+            let new_reserve_in = reserve_in.checked_add(amount_in_after_fee)?;
+            let new_reserve_out = compute_stable_y(amp, new_reserve_in, d)?;
+            // Subtract one unit so rounding in the Newton iteration favors the pool
+            (reserve_out as u128)
+                .checked_sub(new_reserve_out)?
+                .checked_sub(1)
+                .and_then(|amount| u64::try_from(amount).ok())
+        }
+        PoolType::Weighted => {
+            let (weight_in, weight_out) = pool.swap_weights(a_to_b, now);
+            calculate_weighted_swap_output(
+                reserve_in,
+                reserve_out,
+                weight_in,
+                weight_out,
+                amount_in_after_fee,
+            )
+        }
+        PoolType::Pmm => {
+            calculate_pmm_swap_output(pool, a_to_b, reserve_in, reserve_out, amount_in_after_fee)
+        }
+        // Concentrated pools are priced by walking initialized ticks, see `swap_concentrated`
+        PoolType::Concentrated => None,
+    }
+}
+
+// Net input (after fee) required to take `amount_out` of the output token, rounded up
+pub fn calculate_swap_input(
+    pool: &Pool,
+    a_to_b: bool,
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_out: u64,
+    now: i64,
+) -> Option<u64> {
+    let new_reserve_out = reserve_out.checked_sub(amount_out)?;
+    if new_reserve_out == 0 {
+        return None;
+    }
+
+    match pool.pool_type {
+        PoolType::ConstantProduct => {
+            // amount_in = ceil(reserve_in * amount_out / (reserve_out - amount_out))
+            let numerator = (reserve_in as u128).checked_mul(amount_out as u128)?;
+            let amount_in = numerator
+                .checked_add(new_reserve_out as u128 - 1)?
+                .checked_div(new_reserve_out as u128)?;
+            u64::try_from(amount_in).ok()
+        }
+        PoolType::StableSwap => {
+            let amp = pool.current_amp(now);
+            let d = compute_stable_d(amp, reserve_in, reserve_out)?;
+            let new_reserve_in = compute_stable_y(amp, new_reserve_out, d)?;
+            // Add one unit so rounding in the Newton iteration favors the pool
+            new_reserve_in
+                .checked_sub(reserve_in as u128)?
+                .checked_add(1)
+                .and_then(|amount| u64::try_from(amount).ok())
+        }
+        PoolType::Weighted => {
+            let (weight_in, weight_out) = pool.swap_weights(a_to_b, now);
+            calculate_weighted_swap_input(
+                reserve_in,
+                reserve_out,
+                weight_in,
+                weight_out,
+                amount_out,
+            )
+        }
+        PoolType::Pmm => {
+            calculate_pmm_swap_input(pool, a_to_b, reserve_in, reserve_out, amount_out)
+        }
+        PoolType::Concentrated => None,
+    }
+}
+
+// Gross input whose fee-deducted amount covers `amount_in_after_fee`, rounded up
+pub fn calculate_amount_before_fee(
+    pool: &Pool,
+    fee_numerator: u64,
+    amount_in_after_fee: u64,
+) -> Option<u64> {
+    let fee_complement = pool.fee_denominator.checked_sub(fee_numerator)? as u128;
+    let amount_in = (amount_in_after_fee as u128)
+        .checked_mul(pool.fee_denominator as u128)?
+        .checked_add(fee_complement - 1)?
+        .checked_div(fee_complement)?;
+    u64::try_from(amount_in).ok()
+}
+
+// Reserves a PMM pool trades against: the real reserves plus offsets of (1 / k - 1) times
+// the balanced reserves, which hold the pool's value half in each token at the oracle price.
+// The marginal price is the oracle's whenever the pool is balanced and moves away from it
+// as inventory builds up, more slowly the smaller k is. Without an oracle price the
+// offsets are zero and the pool prices like constant product
+pub fn pmm_virtual_reserves(pool: &Pool, reserve_a: u64, reserve_b: u64) -> Option<(U256, U256)> {
+    let reserve_a = U256::from(reserve_a);
+    let reserve_b = U256::from(reserve_b);
+    if pool.pmm_price_x64 == 0 {
+        return Some((reserve_a, reserve_b));
+    }
+
+    let price_x64 = U256::from(pool.pmm_price_x64);
+    let two = U256::from(2u8);
+    let balanced_a = reserve_a.checked_add((reserve_b << 64).checked_div(price_x64)?)? / two;
+    let balanced_b = (reserve_a.checked_mul(price_x64)? >> 64).checked_add(reserve_b)? / two;
+    let k = U256::from(pool.pmm_k);
+    let offset_scale = U256::from(PMM_K_ONE.checked_sub(pool.pmm_k)?);
+    Some((
+        reserve_a.checked_add(balanced_a.checked_mul(offset_scale)?.checked_div(k)?)?,
+        reserve_b.checked_add(balanced_b.checked_mul(offset_scale)?.checked_div(k)?)?,
+    ))
+}
+
+// Output of a PMM swap, constant product over the virtual reserves. Only the real
+// output reserve can be paid out
+pub fn calculate_pmm_swap_output(
+    pool: &Pool,
+    a_to_b: bool,
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in_after_fee: u64,
+) -> Option<u64> {
+    let (virtual_in, virtual_out) = pmm_swap_reserves(pool, a_to_b, reserve_in, reserve_out)?;
+    let new_virtual_in = virtual_in.checked_add(U256::from(amount_in_after_fee))?;
+    // Round the new output reserve up so rounding favors the pool
+    let new_virtual_out = div_round_up(virtual_in.checked_mul(virtual_out)?, new_virtual_in)?;
+    let amount_out = u64::try_from(virtual_out.checked_sub(new_virtual_out)?).ok()?;
+    (amount_out < reserve_out).then_some(amount_out)
+}
+
+// Net input (after fee) a PMM swap needs to take `amount_out`, rounded up
+pub fn calculate_pmm_swap_input(
+    pool: &Pool,
+    a_to_b: bool,
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_out: u64,
+) -> Option<u64> {
+    if amount_out >= reserve_out {
+        return None;
+    }
+    let (virtual_in, virtual_out) = pmm_swap_reserves(pool, a_to_b, reserve_in, reserve_out)?;
+    let amount_in = div_round_up(
+        virtual_in.checked_mul(U256::from(amount_out))?,
+        virtual_out.checked_sub(U256::from(amount_out))?,
+    )?;
+    u64::try_from(amount_in).ok()
+}
+
+// Virtual reserves ordered as (in, out) for a swap in the given direction
+fn pmm_swap_reserves(
+    pool: &Pool,
+    a_to_b: bool,
+    reserve_in: u64,
+    reserve_out: u64,
+) -> Option<(U256, U256)> {
+    if a_to_b {
+        pmm_virtual_reserves(pool, reserve_in, reserve_out)
+    } else {
+        let (virtual_a, virtual_b) = pmm_virtual_reserves(pool, reserve_out, reserve_in)?;
+        Some((virtual_b, virtual_a))
+    }
+}
+
+// LP tokens minted for a one-token deposit into a constant product pool. The deposit is
+// split so that swapping the first part leaves the rest at the pool ratio:
+// swap = (sqrt(r^2 * (2 - f)^2 + 4 * (1 - f) * a * r) - r * (2 - f)) / (2 * (1 - f))
+pub fn calculate_single_sided_lp_tokens(
+    pool: &Pool,
+    fee_numerator: u64,
+    a_to_b: bool,
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    lp_supply: u64,
+    now: i64,
+) -> Option<u64> {
+    let fee_denominator = U256::from(pool.fee_denominator);
+    let fee_complement = fee_denominator.checked_sub(U256::from(fee_numerator))?;
+    let two_minus_fee = fee_denominator.checked_add(fee_complement)?;
+    let reserve = U256::from(reserve_in);
+
+    let r_term = reserve.checked_mul(two_minus_fee)?;
+    let discriminant = r_term.checked_mul(r_term)?.checked_add(
+        U256::from(4u8)
+            .checked_mul(fee_complement)?
+            .checked_mul(fee_denominator)?
+            .checked_mul(U256::from(amount_in))?
+            .checked_mul(reserve)?,
+    )?;
+    let swap_amount = discriminant
+        .integer_sqrt()
+        .checked_sub(r_term)?
+        .checked_div(fee_complement.checked_mul(U256::from(2u8))?)?;
+    let swap_amount = u64::try_from(swap_amount).ok()?.min(amount_in);
+
+    let fee = (swap_amount as u128)
+        .checked_mul(fee_numerator as u128)?
+        .checked_div(pool.fee_denominator as u128)? as u64;
+    let swapped_out = calculate_swap_output(
+        pool,
+        a_to_b,
+        reserve_in,
+        reserve_out,
+        swap_amount.checked_sub(fee)?,
+        now,
+    )?;
+
+    // Deposit the remainder and the swap output against the post-swap reserves
+    let deposit_in = amount_in.checked_sub(swap_amount)?;
+    let new_reserve_in = reserve_in.checked_add(swap_amount)?;
+    let new_reserve_out = reserve_out.checked_sub(swapped_out)?;
+    let lp_from_in = (deposit_in as u128)
+        .checked_mul(lp_supply as u128)?
+        .checked_div(new_reserve_in as u128)?;
+    let lp_from_out = (swapped_out as u128)
+        .checked_mul(lp_supply as u128)?
+        .checked_div(new_reserve_out as u128)?;
+    u64::try_from(std::cmp::min(lp_from_in, lp_from_out)).ok()
+}
+
+// StableSwap invariant for two coins, with Ann = amp * n:
+// Ann * (x + y) + D = Ann * D + D^3 / (4 * x * y)
+const STABLE_N_COINS: u128 = 2;
+const STABLE_MAX_ITERATIONS: usize = 64;
+
+// Solves the invariant for D given both balances using Newton's method
+pub fn compute_stable_d(amp: u64, x: u64, y: u64) -> Option<u128> {
+    let sum = U256::from(x) + U256::from(y);
+    if sum.is_zero() {
+        return Some(0);
+    }
+    if x == 0 || y == 0 {
+        return None;
+    }
+
+    let n = U256::from(STABLE_N_COINS);
+    let ann = U256::from(amp).checked_mul(n)?;
+    let mut d = sum;
+
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        // d_p = D^3 / (n^n * x * y)
+        let mut d_p = d;
+        d_p = d_p.checked_mul(d)?.checked_div(U256::from(x).checked_mul(n)?)?;
+        d_p = d_p.checked_mul(d)?.checked_div(U256::from(y).checked_mul(n)?)?;
+
+        let d_prev = d;
+        let numerator = ann
+            .checked_mul(sum)?
+            .checked_add(d_p.checked_mul(n)?)?
+            .checked_mul(d)?;
+        let denominator = ann
+            .checked_sub(U256::one())?
+            .checked_mul(d)?
+            .checked_add(n.checked_add(U256::one())?.checked_mul(d_p)?)?;
+        d = numerator.checked_div(denominator)?;
+
+        let delta = if d > d_prev { d - d_prev } else { d_prev - d };
+        if delta <= U256::one() {
+            return u128::try_from(d).ok();
+        }
+    }
+
+    None
+}
+
+// Solves the invariant for the other balance given one balance and D
+pub fn compute_stable_y(amp: u64, x: u64, d: u128) -> Option<u128> {
+    if x == 0 {
+        return None;
+    }
+
+    let n = U256::from(STABLE_N_COINS);
+    let ann = U256::from(amp).checked_mul(n)?;
+    let d = U256::from(d);
+
+    // c = D^3 / (n^n * x * Ann), b = x + D / Ann. Rounding c up can only raise y,
+    // so the solved balance never falls short of the invariant
+    let c = div_round_up(
+        div_round_up(d.checked_mul(d)?, U256::from(x).checked_mul(n)?)?.checked_mul(d)?,
+        ann.checked_mul(n)?,
+    )?;
+    let b = U256::from(x).checked_add(d.checked_div(ann)?)?;
+
+    let mut y = d;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let y_prev = y;
+        // y = (y^2 + c) / (2y + b - D)
+        let numerator = y.checked_mul(y)?.checked_add(c)?;
+        let denominator = y
+            .checked_mul(U256::from(2u8))?
+            .checked_add(b)?
+            .checked_sub(d)?;
+        y = numerator.checked_div(denominator)?;
+
+        let delta = if y > y_prev { y - y_prev } else { y_prev - y };
+        if delta <= U256::one() {
+            return u128::try_from(y).ok();
+        }
+    }
+
+    None
+}
+
+// LP tokens minted for a stable pool deposit. Imbalanced deposits pay half the swap
+// fee on the part that deviates from the pool ratio, as a proportional deposit
+// followed by a withdrawal would otherwise act as a fee-free swap.
+pub fn calculate_stable_lp_tokens(
+    pool: &Pool,
+    fee_numerator: u64,
+    amp: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    amount_a: u64,
+    amount_b: u64,
+    lp_supply: u64,
+) -> Option<u64> {
+    let new_reserve_a = reserve_a.checked_add(amount_a)?;
+    let new_reserve_b = reserve_b.checked_add(amount_b)?;
+    let d1 = compute_stable_d(amp, new_reserve_a, new_reserve_b)?;
+
+    if lp_supply == 0 {
+        return u64::try_from(d1).ok();
+    }
+
+    let d0 = compute_stable_d(amp, reserve_a, reserve_b)?;
+    if d0 == 0 {
+        return None;
+    }
+
+    let imbalance_fee = |reserve: u64, new_reserve: u64| -> Option<u64> {
+        let ideal = d1.checked_mul(reserve as u128)?.checked_div(d0)?;
+        let difference = if ideal > new_reserve as u128 {
+            ideal - new_reserve as u128
+        } else {
+            new_reserve as u128 - ideal
+        };
+        let fee = difference
+            .checked_mul(fee_numerator as u128)?
+            .checked_div(pool.fee_denominator.checked_mul(2)? as u128)?;
+        u64::try_from(fee).ok()
+    };
+    let adjusted_a = new_reserve_a.checked_sub(imbalance_fee(reserve_a, new_reserve_a)?)?;
+    let adjusted_b = new_reserve_b.checked_sub(imbalance_fee(reserve_b, new_reserve_b)?)?;
+    let d2 = compute_stable_d(amp, adjusted_a, adjusted_b)?;
+
+    let lp_tokens = (lp_supply as u128)
+        .checked_mul(d2.checked_sub(d0)?)?
+        .checked_div(d0)?;
+    u64::try_from(lp_tokens).ok()
+}
+
+// Splits `weight_a : weight_b` into normalized weights summing to WEIGHT_ONE
+pub fn normalize_weights(weight_a: u64, weight_b: u64) -> Option<(u64, u64)> {
+    let total = (weight_a as u128).checked_add(weight_b as u128)?;
+    if total == 0 {
+        return None;
+    }
+    let normalized_a = (weight_a as u128 * WEIGHT_ONE as u128 / total) as u64;
+    let normalized_b = WEIGHT_ONE - normalized_a;
+    if normalized_a < MIN_WEIGHT || normalized_b < MIN_WEIGHT {
+        return None;
+    }
+    Some((normalized_a, normalized_b))
+}
+
+// Weighted pool math. The invariant is reserve_a^weight_a * reserve_b^weight_b with
+// normalized weights, so fractional powers are taken through log2 and exp2 in Q64.64.
+
+// ln(2) as Q64.64
+const LN_2_X64: u128 = 12_786_308_645_202_655_659;
+const EXP_TAYLOR_TERMS: u128 = 24;
+// Relative error bound of `pow_x64`, as a right shift, applied when rounding for the pool
+const POW_ERROR_SHIFT: u32 = 48;
+
+// log2(x) for a Q64.64 x > 0 as signed Q64.64. The integer part comes from the bit length,
+// each fractional bit from squaring the mantissa normalized into [1, 2)
+pub fn log2_x64(x_x64: u128) -> Option<i128> {
+    if x_x64 == 0 {
+        return None;
+    }
+
+    let msb = 127 - x_x64.leading_zeros();
+    let mut result = (msb as i128 - 64) << 64;
+    let mut mantissa = if msb >= 64 { x_x64 >> (msb - 64) } else { x_x64 << (64 - msb) };
+    for bit in (0..64).rev() {
+        mantissa = u128::try_from((U256::from(mantissa) * U256::from(mantissa)) >> 64).ok()?;
+        if mantissa >= 2 << 64 {
+            mantissa >>= 1;
+            result += 1 << bit;
+        }
+    }
+    Some(result)
+}
+
+// 2^y for a signed Q64.64 y as Q64.64, None if it does not fit. The fractional part is
+// evaluated as e^(f * ln 2) by its Taylor series, which converges quickly for f * ln 2 < 0.7
+pub fn exp2_x64(y_x64: i128) -> Option<u128> {
+    let integer = y_x64 >> 64;
+    let fraction = (y_x64 - (integer << 64)) as u128;
+    let x = (U256::from(fraction) * U256::from(LN_2_X64)) >> 64;
+
+    let one = U256::one() << 64;
+    let mut term = one;
+    let mut sum = one;
+    for n in 1..=EXP_TAYLOR_TERMS {
+        term = ((term * x) >> 64) / U256::from(n);
+        if term.is_zero() {
+            break;
+        }
+        sum += term;
+    }
+
+    if integer >= 0 {
+        if integer > 63 {
+            return None;
+        }
+        u128::try_from(sum << integer as usize).ok()
+    } else if integer < -128 {
+        Some(0)
+    } else {
+        u128::try_from(sum >> (-integer) as usize).ok()
+    }
+}
+
+// base^exponent for Q64.64 base and exponent
+pub fn pow_x64(base_x64: u128, exponent_x64: u128) -> Option<u128> {
+    let log = log2_x64(base_x64)?;
+    let magnitude = (U256::from(log.unsigned_abs()) * U256::from(exponent_x64)) >> 64;
+    let magnitude = i128::try_from(u128::try_from(magnitude).ok()?).ok()?;
+    exp2_x64(if log < 0 { -magnitude } else { magnitude })
+}
+
+// `pow_x64` rounded past its error bound, up or down
+fn pow_x64_up(base_x64: u128, exponent_x64: u128) -> Option<u128> {
+    let power = pow_x64(base_x64, exponent_x64)?;
+    power.checked_add((power >> POW_ERROR_SHIFT) + 1)
+}
+
+fn pow_x64_down(base_x64: u128, exponent_x64: u128) -> Option<u128> {
+    let power = pow_x64(base_x64, exponent_x64)?;
+    Some(power.saturating_sub((power >> POW_ERROR_SHIFT) + 1))
+}
+
+// reserve_a^weight_a * reserve_b^weight_b in token units, rounded down
+pub fn calculate_weighted_invariant(
+    reserve_a: u64,
+    reserve_b: u64,
+    weight_a: u64,
+    weight_b: u64,
+) -> Option<u64> {
+    let log_a = log2_x64((reserve_a as u128) << 64)?;
+    let log_b = log2_x64((reserve_b as u128) << 64)?;
+    // Reserves are at least one unit, so both logs are non-negative
+    let log = (log_a as u128)
+        .checked_mul(weight_a as u128)?
+        .checked_add((log_b as u128).checked_mul(weight_b as u128)?)?
+        / WEIGHT_ONE as u128;
+    let invariant_x64 = exp2_x64(i128::try_from(log).ok()?)?;
+    u64::try_from(invariant_x64.saturating_sub((invariant_x64 >> POW_ERROR_SHIFT) + 1) >> 64).ok()
+}
+
+// amount_out =
+//     reserve_out * (1 - (reserve_in / (reserve_in + amount_in)) ^ (weight_in / weight_out)),
+// with the power rounded up so the pool never pays out more than the invariant allows
+pub fn calculate_weighted_swap_output(
+    reserve_in: u64,
+    reserve_out: u64,
+    weight_in: u64,
+    weight_out: u64,
+    amount_in_after_fee: u64,
+) -> Option<u64> {
+    let new_reserve_in = reserve_in.checked_add(amount_in_after_fee)?;
+    let base_x64 = div_round_up(U256::from(reserve_in) << 64, U256::from(new_reserve_in))?;
+    let exponent_x64 = ((weight_in as u128) << 64).checked_div(weight_out as u128)?;
+    let power_x64 = pow_x64_up(u128::try_from(base_x64).ok()?, exponent_x64)?.min(1 << 64);
+    let amount_out = (U256::from(reserve_out) * U256::from((1u128 << 64) - power_x64)) >> 64;
+    u64::try_from(amount_out).ok()
+}
+
+// amount_in =
+//     reserve_in * ((reserve_out / (reserve_out - amount_out)) ^ (weight_out / weight_in) - 1),
+// rounded up. The caller guarantees amount_out < reserve_out
+pub fn calculate_weighted_swap_input(
+    reserve_in: u64,
+    reserve_out: u64,
+    weight_in: u64,
+    weight_out: u64,
+    amount_out: u64,
+) -> Option<u64> {
+    let new_reserve_out = reserve_out.checked_sub(amount_out)?;
+    let base_x64 = div_round_up(U256::from(reserve_out) << 64, U256::from(new_reserve_out))?;
+    let exponent_x64 = ((weight_out as u128) << 64).checked_div(weight_in as u128)?;
+    let power_x64 = pow_x64_up(u128::try_from(base_x64).ok()?, exponent_x64)?;
+    let amount_in = div_round_up(
+        U256::from(reserve_in).checked_mul(U256::from(power_x64.checked_sub(1 << 64)?))?,
+        U256::one() << 64,
+    )?;
+    u64::try_from(amount_in).ok()
+}
+
+// LP tokens minted for a one-token deposit into a weighted pool. Only the share of the
+// deposit the pool would have to swap into the other token, (1 - weight_in), pays the fee:
+// lp = supply * ((1 + amount_in_after_fee / reserve_in) ^ weight_in - 1)
+pub fn calculate_weighted_single_sided_lp_tokens(
+    fee_numerator: u64,
+    fee_denominator: u64,
+    reserve_in: u64,
+    weight_in: u64,
+    amount_in: u64,
+    lp_supply: u64,
+) -> Option<u64> {
+    let taxable = (amount_in as u128)
+        .checked_mul((WEIGHT_ONE - weight_in) as u128)?
+        .checked_div(WEIGHT_ONE as u128)?;
+    let fee = taxable
+        .checked_mul(fee_numerator as u128)?
+        .checked_add(fee_denominator as u128 - 1)?
+        .checked_div(fee_denominator as u128)?;
+    let amount_in_after_fee = (amount_in as u128).checked_sub(fee)?;
+
+    let base_x64 = (U256::from(reserve_in as u128 + amount_in_after_fee) << 64)
+        .checked_div(U256::from(reserve_in))?;
+    let exponent_x64 = ((weight_in as u128) << 64) / WEIGHT_ONE as u128;
+    let power_x64 = pow_x64_down(u128::try_from(base_x64).ok()?, exponent_x64)?;
+    let lp_tokens = (U256::from(lp_supply)
+        .checked_mul(U256::from(power_x64.saturating_sub(1 << 64)))?)
+        >> 64;
+    u64::try_from(lp_tokens).ok()
+}
+
+// Token out for burning `lp_amount` against one token of a weighted pool, the inverse of
+// the single-sided deposit: reserve_out * (1 - (1 - lp_amount / lp_supply) ^ (1 / weight_out)),
+// with the fee charged on the (1 - weight_out) share the pool gives up of the other tokens
+pub fn calculate_weighted_single_sided_withdrawal(
+    fee_numerator: u64,
+    fee_denominator: u64,
+    reserve_out: u64,
+    weight_out: u64,
+    lp_amount: u64,
+    lp_supply: u64,
+) -> Option<u64> {
+    let remaining_supply = lp_supply.checked_sub(lp_amount)?;
+    let base_x64 = div_round_up(U256::from(remaining_supply) << 64, U256::from(lp_supply))?;
+    let exponent_x64 = ((WEIGHT_ONE as u128) << 64).checked_div(weight_out as u128)?;
+    let power_x64 = pow_x64_up(u128::try_from(base_x64).ok()?, exponent_x64)?.min(1 << 64);
+    let amount_out = (U256::from(reserve_out) * U256::from((1u128 << 64) - power_x64)) >> 64;
+    let amount_out = u128::try_from(amount_out).ok()?;
+
+    let taxable = amount_out
+        .checked_mul((WEIGHT_ONE - weight_out) as u128)?
+        .checked_div(WEIGHT_ONE as u128)?;
+    let fee = taxable
+        .checked_mul(fee_numerator as u128)?
+        .checked_add(fee_denominator as u128 - 1)?
+        .checked_div(fee_denominator as u128)?;
+    u64::try_from(amount_out.checked_sub(fee)?).ok()
+}
+
+// n-th root of the product of `amounts` in token units, rounded down
+pub fn calculate_geometric_mean(amounts: &[u64]) -> Option<u64> {
+    if amounts.is_empty() {
+        return None;
+    }
+
+    // Amounts are at least one unit, so every log is non-negative
+    let mut log_sum: u128 = 0;
+    for amount in amounts {
+        log_sum = log_sum.checked_add(log2_x64((*amount as u128) << 64)? as u128)?;
+    }
+    let mean_x64 = exp2_x64(i128::try_from(log_sum / amounts.len() as u128).ok()?)?;
+    u64::try_from(mean_x64.saturating_sub((mean_x64 >> POW_ERROR_SHIFT) + 1) >> 64).ok()
+}
+
+// Multi-asset pool math. Constant product pools keep the product of all reserves, so a swap
+// between two members is the two-token formula on their reserves. Stable pools use the
+// StableSwap invariant for n coins, with Ann = amp * n:
+// Ann * sum(x) + D = Ann * D + D^(n + 1) / (n^n * prod(x))
+
+// Amount of member `index_out` released for `amount_in_after_fee` of member `index_in`
+pub fn calculate_multi_swap_output(
+    pool: &MultiPool,
+    index_in: usize,
+    index_out: usize,
+    amount_in_after_fee: u64,
+) -> Option<u64> {
+    let reserves = pool.active_reserves();
+    let reserve_in = reserves[index_in];
+    let reserve_out = reserves[index_out];
+    match pool.pool_type {
+        PoolType::ConstantProduct => {
+            let new_reserve_in = reserve_in.checked_add(amount_in_after_fee)?;
+            let product = (reserve_in as u128).checked_mul(reserve_out as u128)?;
+            let new_reserve_out = product.checked_div(new_reserve_in as u128)? as u64;
+            reserve_out.checked_sub(new_reserve_out)
+        }
+        PoolType::StableSwap => {
+            let d = compute_stable_d_n(pool.amp, reserves)?;
+            let mut balances = reserves.to_vec();
+            balances[index_in] = reserve_in.checked_add(amount_in_after_fee)?;
+            let new_reserve_out = compute_stable_y_n(pool.amp, index_out, &balances, d)?;
+            // Subtract one unit so rounding in the Newton iteration favors the pool
+            (reserve_out as u128)
+                .checked_sub(new_reserve_out)?
+                .checked_sub(1)
+                .and_then(|amount| u64::try_from(amount).ok())
+        }
+        _ => None,
+    }
+}
+
+// LP tokens minted for depositing `amounts` of every member. Constant product pools mint the
+// geometric mean on the first deposit and the smallest share of the reserves afterwards
+pub fn calculate_multi_lp_tokens(pool: &MultiPool, amounts: &[u64], lp_supply: u64) -> Option<u64> {
+    let reserves = pool.active_reserves();
+    match pool.pool_type {
+        PoolType::ConstantProduct => {
+            if lp_supply == 0 {
+                return calculate_geometric_mean(amounts);
+            }
+            let mut lp_tokens = u128::MAX;
+            for (amount, reserve) in amounts.iter().zip(reserves) {
+                let lp_amount = (*amount as u128)
+                    .checked_mul(lp_supply as u128)?
+                    .checked_div(*reserve as u128)?;
+                lp_tokens = lp_tokens.min(lp_amount);
+            }
+            u64::try_from(lp_tokens).ok()
+        }
+        PoolType::StableSwap => calculate_stable_multi_lp_tokens(pool, amounts, lp_supply),
+        _ => None,
+    }
+}
+
+// Solves the n-coin invariant for D given every balance using Newton's method
+pub fn compute_stable_d_n(amp: u64, balances: &[u64]) -> Option<u128> {
+    let sum = balances
+        .iter()
+        .try_fold(U256::zero(), |sum, balance| sum.checked_add(U256::from(*balance)))?;
+    if sum.is_zero() {
+        return Some(0);
+    }
+    if balances.iter().any(|balance| *balance == 0) {
+        return None;
+    }
+
+    let n = U256::from(balances.len());
+    let ann = U256::from(amp).checked_mul(n)?;
+    let mut d = sum;
+
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        // d_p = D^(n + 1) / (n^n * prod(x))
+        let mut d_p = d;
+        for balance in balances {
+            d_p = d_p.checked_mul(d)?.checked_div(U256::from(*balance).checked_mul(n)?)?;
+        }
+
+        let d_prev = d;
+        let numerator = ann
+            .checked_mul(sum)?
+            .checked_add(d_p.checked_mul(n)?)?
+            .checked_mul(d)?;
+        let denominator = ann
+            .checked_sub(U256::one())?
+            .checked_mul(d)?
+            .checked_add(n.checked_add(U256::one())?.checked_mul(d_p)?)?;
+        d = numerator.checked_div(denominator)?;
+
+        let delta = if d > d_prev { d - d_prev } else { d_prev - d };
+        if delta <= U256::one() {
+            return u128::try_from(d).ok();
+        }
+    }
+
+    None
+}
+
+// Solves the n-coin invariant for the balance at `index` given the other balances and D.
+// The balance at `index` itself is ignored
+pub fn compute_stable_y_n(amp: u64, index: usize, balances: &[u64], d: u128) -> Option<u128> {
+    let n = U256::from(balances.len());
+    let ann = U256::from(amp).checked_mul(n)?;
+    let d = U256::from(d);
+
+    // c = D^(n + 1) / (n^n * prod(x_k) * Ann), b = sum(x_k) + D / Ann over k != index.
+    // Rounding c up can only raise y, so the solved balance never falls short of the invariant
+    let mut c = d;
+    let mut sum = U256::zero();
+    for (k, balance) in balances.iter().enumerate() {
+        if k == index {
+            continue;
+        }
+        if *balance == 0 {
+            return None;
+        }
+        sum = sum.checked_add(U256::from(*balance))?;
+        c = div_round_up(c.checked_mul(d)?, U256::from(*balance).checked_mul(n)?)?;
+    }
+    c = div_round_up(c.checked_mul(d)?, ann.checked_mul(n)?)?;
+    let b = sum.checked_add(d.checked_div(ann)?)?;
+
+    let mut y = d;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let y_prev = y;
+        // y = (y^2 + c) / (2y + b - D)
+        let numerator = y.checked_mul(y)?.checked_add(c)?;
+        let denominator = y
+            .checked_mul(U256::from(2u8))?
+            .checked_add(b)?
+            .checked_sub(d)?;
+        y = numerator.checked_div(denominator)?;
+
+        let delta = if y > y_prev { y - y_prev } else { y_prev - y };
+        if delta <= U256::one() {
+            return u128::try_from(y).ok();
+        }
+    }
+
+    None
+}
+
+// Fee charged on each member's deviation from the pool ratio in stable deposits and single-sided
+// withdrawals, fee * n / (4 * (n - 1)) as (numerator, denominator). At n = 2 this is the half
+// fee two-token stable pools charge
+fn stable_imbalance_fee(pool: &MultiPool) -> (u128, u128) {
+    let n = pool.token_count as u128;
+    (
+        pool.fee_numerator as u128 * n,
+        pool.fee_denominator as u128 * 4 * (n - 1),
+    )
+}
+
+// LP tokens minted for a stable multi-asset deposit, any member amount may be zero
+pub fn calculate_stable_multi_lp_tokens(
+    pool: &MultiPool,
+    amounts: &[u64],
+    lp_supply: u64,
+) -> Option<u64> {
+    let reserves = pool.active_reserves();
+    let new_reserves = reserves
+        .iter()
+        .zip(amounts)
+        .map(|(reserve, amount)| reserve.checked_add(*amount))
+        .collect::<Option<Vec<u64>>>()?;
+    let d1 = compute_stable_d_n(pool.amp, &new_reserves)?;
+
+    if lp_supply == 0 {
+        return u64::try_from(d1).ok();
+    }
+
+    let d0 = compute_stable_d_n(pool.amp, reserves)?;
+    if d0 == 0 {
+        return None;
+    }
+
+    let (fee_numerator, fee_denominator) = stable_imbalance_fee(pool);
+    let adjusted = reserves
+        .iter()
+        .zip(&new_reserves)
+        .map(|(reserve, new_reserve)| {
+            let ideal = d1.checked_mul(*reserve as u128)?.checked_div(d0)?;
+            let fee = ideal
+                .abs_diff(*new_reserve as u128)
+                .checked_mul(fee_numerator)?
+                .checked_div(fee_denominator)?;
+            new_reserve.checked_sub(u64::try_from(fee).ok()?)
+        })
+        .collect::<Option<Vec<u64>>>()?;
+    let d2 = compute_stable_d_n(pool.amp, &adjusted)?;
+
+    let lp_tokens = (lp_supply as u128)
+        .checked_mul(d2.checked_sub(d0)?)?
+        .checked_div(d0)?;
+    u64::try_from(lp_tokens).ok()
+}
+
+// Amount of member `index` paid out for burning `lp_amount` of a stable multi-asset pool.
+// The pool shrinks D in proportion, solves for the new balance of `index`, and charges the
+// imbalance fee on how far each member moves from a proportional withdrawal
+pub fn calculate_stable_multi_withdraw_one(
+    pool: &MultiPool,
+    index: usize,
+    lp_amount: u64,
+    lp_supply: u64,
+) -> Option<u64> {
+    let reserves = pool.active_reserves();
+    let d0 = compute_stable_d_n(pool.amp, reserves)?;
+    let d1 = d0.checked_sub(
+        d0.checked_mul(lp_amount as u128)?
+            .checked_div(lp_supply as u128)?,
+    )?;
+    let new_balance = compute_stable_y_n(pool.amp, index, reserves, d1)?;
+
+    let (fee_numerator, fee_denominator) = stable_imbalance_fee(pool);
+    let reduced = reserves
+        .iter()
+        .enumerate()
+        .map(|(k, reserve)| {
+            let expected = (*reserve as u128).checked_mul(d1)?.checked_div(d0)?;
+            let deviation = if k == index {
+                expected.saturating_sub(new_balance)
+            } else {
+                (*reserve as u128).checked_sub(expected)?
+            };
+            let fee = deviation.checked_mul(fee_numerator)?.checked_div(fee_denominator)?;
+            reserve.checked_sub(u64::try_from(fee).ok()?)
+        })
+        .collect::<Option<Vec<u64>>>()?;
+    let reduced_balance = compute_stable_y_n(pool.amp, index, &reduced, d1)?;
+
+    // Subtract one unit so rounding in the Newton iteration favors the pool
+    (reduced[index] as u128)
+        .checked_sub(reduced_balance)?
+        .checked_sub(1)
+        .and_then(|amount| u64::try_from(amount).ok())
+}
+
+// Concentrated liquidity math. Sqrt prices are Q64.64 and token A is the base,
+// so selling token A moves the price down.
+
+// floor(2^128 / sqrt(1.0001)^(2^i)) for each bit of an absolute tick index, the same
+// factors Uniswap v3 uses
+const SQRT_PRICE_FACTORS_X128: [u128; 20] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x09aa508b5b7a84e1c677de54f3e99bc9,
+    0x005d6af8dedb81196699c329225ee604,
+    0x00002216e584f5fa1ea926041bedfe98,
+    0x00000000048a170391f7dc42444e8fa2,
+];
+
+// sqrt(1.0001^tick) as Q64.64. The product is kept at 128 fractional bits and rounded
+// up, so positive ticks keep their precision through the inversion
+pub fn sqrt_price_at_tick(tick: i32) -> Option<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = U256::one() << 128;
+    for (bit, factor) in SQRT_PRICE_FACTORS_X128.iter().enumerate() {
+        if abs_tick & (1 << bit) != 0 {
+            ratio = ratio.checked_mul(U256::from(*factor))? >> 128;
+        }
+    }
+
+    // The factors are reciprocals, so positive ticks invert the product
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    u128::try_from(div_round_up(ratio, U256::one() << 64)?).ok()
+}
+
+// Greatest tick whose sqrt price does not exceed `sqrt_price_x64`
+pub fn tick_at_sqrt_price(sqrt_price_x64: u128) -> i32 {
+    let mut low = MIN_TICK;
+    let mut high = MAX_TICK;
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        match sqrt_price_at_tick(mid) {
+            Some(price) if price <= sqrt_price_x64 => low = mid,
+            _ => high = mid - 1,
+        }
+    }
+    low
+}
+
+fn div_round_up(numerator: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let quotient = numerator / denominator;
+    if (numerator % denominator).is_zero() {
+        Some(quotient)
+    } else {
+        quotient.checked_add(U256::one())
+    }
+}
+
+// Token A backing `liquidity` between two sqrt prices: L * (upper - lower) / (upper * lower)
+pub fn get_amount_a_delta(
+    sqrt_price_0: u128,
+    sqrt_price_1: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    let (lower, upper) = if sqrt_price_0 < sqrt_price_1 {
+        (sqrt_price_0, sqrt_price_1)
+    } else {
+        (sqrt_price_1, sqrt_price_0)
+    };
+    if lower == 0 {
+        return None;
+    }
+
+    let numerator = (U256::from(liquidity) << 64).checked_mul(U256::from(upper - lower))?;
+    let amount = if round_up {
+        div_round_up(div_round_up(numerator, U256::from(upper))?, U256::from(lower))?
+    } else {
+        numerator / U256::from(upper) / U256::from(lower)
+    };
+    u128::try_from(amount).ok()
+}
+
+// Token B backing `liquidity` between two sqrt prices: L * (upper - lower)
+pub fn get_amount_b_delta(
+    sqrt_price_0: u128,
+    sqrt_price_1: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    let (lower, upper) = if sqrt_price_0 < sqrt_price_1 {
+        (sqrt_price_0, sqrt_price_1)
+    } else {
+        (sqrt_price_1, sqrt_price_0)
+    };
+
+    let product = U256::from(liquidity).checked_mul(U256::from(upper - lower))?;
+    let amount = if round_up {
+        div_round_up(product, U256::one() << 64)?
+    } else {
+        product >> 64
+    };
+    u128::try_from(amount).ok()
+}
+
+// Sqrt price reached after adding `amount_in` of the input token, rounded against the trader
+fn get_next_sqrt_price_from_input(
+    sqrt_price_x64: u128,
+    liquidity: u128,
+    amount_in: u128,
+    a_to_b: bool,
+) -> Option<u128> {
+    if a_to_b {
+        // sqrt_price' = L * sqrt_price / (L + amount_in * sqrt_price)
+        let liquidity_shifted = U256::from(liquidity) << 64;
+        let numerator = liquidity_shifted.checked_mul(U256::from(sqrt_price_x64))?;
+        let denominator = liquidity_shifted
+            .checked_add(U256::from(amount_in).checked_mul(U256::from(sqrt_price_x64))?)?;
+        u128::try_from(div_round_up(numerator, denominator)?).ok()
+    } else {
+        // sqrt_price' = sqrt_price + amount_in / L
+        let delta = (U256::from(amount_in) << 64).checked_div(U256::from(liquidity))?;
+        u128::try_from(U256::from(sqrt_price_x64).checked_add(delta)?).ok()
+    }
+}
+
+pub struct SwapStep {
+    pub sqrt_price_next_x64: u128,
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub fee_amount: u128,
+}
+
+// Swaps as much of `amount_remaining` as fits before the price reaches `sqrt_price_target_x64`
+pub fn compute_swap_step(
+    sqrt_price_current_x64: u128,
+    sqrt_price_target_x64: u128,
+    liquidity: u128,
+    amount_remaining: u128,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    a_to_b: bool,
+) -> Option<SwapStep> {
+    let fee_numerator = fee_numerator as u128;
+    let fee_denominator = fee_denominator as u128;
+    let fee_complement = fee_denominator.checked_sub(fee_numerator)?;
+
+    let amount_remaining_less_fee = amount_remaining
+        .checked_mul(fee_complement)?
+        .checked_div(fee_denominator)?;
+
+    // An overflowing maximum can never be filled by a u64 input
+    let amount_in_max = if a_to_b {
+        get_amount_a_delta(sqrt_price_target_x64, sqrt_price_current_x64, liquidity, true)
+    } else {
+        get_amount_b_delta(sqrt_price_current_x64, sqrt_price_target_x64, liquidity, true)
+    }
+    .unwrap_or(u128::MAX);
+
+    let (sqrt_price_next_x64, amount_in) = if amount_remaining_less_fee >= amount_in_max {
+        (sqrt_price_target_x64, amount_in_max)
+    } else {
+        let sqrt_price_next_x64 = get_next_sqrt_price_from_input(
+            sqrt_price_current_x64,
+            liquidity,
+            amount_remaining_less_fee,
+            a_to_b,
+        )?;
+        let amount_in = if a_to_b {
+            get_amount_a_delta(sqrt_price_next_x64, sqrt_price_current_x64, liquidity, true)?
+        } else {
+            get_amount_b_delta(sqrt_price_current_x64, sqrt_price_next_x64, liquidity, true)?
+        };
+        (sqrt_price_next_x64, amount_in)
+    };
+
+    let amount_out = if a_to_b {
+        get_amount_b_delta(sqrt_price_next_x64, sqrt_price_current_x64, liquidity, false)?
+    } else {
+        get_amount_a_delta(sqrt_price_current_x64, sqrt_price_next_x64, liquidity, false)?
+    };
+
+    // When the target is not reached the whole remainder is consumed, so the rest is fee
+    let fee_amount = if sqrt_price_next_x64 != sqrt_price_target_x64 {
+        amount_remaining.checked_sub(amount_in)?
+    } else {
+        let fee = U256::from(amount_in).checked_mul(U256::from(fee_numerator))?;
+        u128::try_from(div_round_up(fee, U256::from(fee_complement))?).ok()?
+    };
+
+    Some(SwapStep {
+        sqrt_price_next_x64,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+fn add_liquidity_delta(liquidity: u128, delta: i128) -> Option<u128> {
+    if delta < 0 {
+        liquidity.checked_sub(delta.unsigned_abs())
+    } else {
+        liquidity.checked_add(delta as u128)
+    }
+}
+
+// Fee growth per unit of liquidity earned strictly inside [tick_lower, tick_upper)
+fn fee_growth_inside(
+    pool: &Pool,
+    lower: &Tick,
+    upper: &Tick,
+    tick_lower: i32,
+    tick_upper: i32,
+) -> (u128, u128) {
+    let global_a = pool.fee_growth_global_a_x64;
+    let global_b = pool.fee_growth_global_b_x64;
+
+    let (below_a, below_b) = if pool.tick_current >= tick_lower {
+        (lower.fee_growth_outside_a_x64, lower.fee_growth_outside_b_x64)
+    } else {
+        (
+            global_a.wrapping_sub(lower.fee_growth_outside_a_x64),
+            global_b.wrapping_sub(lower.fee_growth_outside_b_x64),
+        )
+    };
+    let (above_a, above_b) = if pool.tick_current < tick_upper {
+        (upper.fee_growth_outside_a_x64, upper.fee_growth_outside_b_x64)
+    } else {
+        (
+            global_a.wrapping_sub(upper.fee_growth_outside_a_x64),
+            global_b.wrapping_sub(upper.fee_growth_outside_b_x64),
+        )
+    };
+
+    (
+        global_a.wrapping_sub(below_a).wrapping_sub(above_a),
+        global_b.wrapping_sub(below_b).wrapping_sub(above_b),
+    )
+}
+
+fn update_tick(
+    pool: &Pool,
+    tick: &mut Tick,
+    tick_index: i32,
+    liquidity_delta: i128,
+    is_upper: bool,
+) -> Result<()> {
+    let liquidity_gross = tick.liquidity_gross;
+    if liquidity_gross == 0 {
+        // By convention all fees earned before a tick is initialized happened below it
+        if tick_index <= pool.tick_current {
+            tick.fee_growth_outside_a_x64 = pool.fee_growth_global_a_x64;
+            tick.fee_growth_outside_b_x64 = pool.fee_growth_global_b_x64;
+        }
+    }
+
+    tick.liquidity_gross =
+        add_liquidity_delta(liquidity_gross, liquidity_delta).ok_or(ErrorCode::MathOverflow)?;
+
+    // Crossing the lower tick upwards adds the position's liquidity, crossing the upper removes it
+    let liquidity_net = tick.liquidity_net;
+    tick.liquidity_net = if is_upper {
+        liquidity_net.checked_sub(liquidity_delta)
+    } else {
+        liquidity_net.checked_add(liquidity_delta)
+    }
+    .ok_or(ErrorCode::MathOverflow)?;
+
+    Ok(())
+}
+
+fn update_position_ticks(
+    pool: &Pool,
+    position: &mut Position,
+    lower: &mut Tick,
+    upper: &mut Tick,
+    liquidity_delta: i128,
+) -> Result<()> {
+    if liquidity_delta != 0 {
+        update_tick(pool, lower, position.tick_lower, liquidity_delta, false)?;
+        update_tick(pool, upper, position.tick_upper, liquidity_delta, true)?;
+    }
+
+    // Accrue the fees earned since the position was last touched
+    let (inside_a, inside_b) =
+        fee_growth_inside(pool, lower, upper, position.tick_lower, position.tick_upper);
+    let earned_a = U256::from(position.liquidity)
+        .checked_mul(U256::from(inside_a.wrapping_sub(position.fee_growth_inside_a_last_x64)))
+        .ok_or(ErrorCode::MathOverflow)?
+        >> 64;
+    let earned_b = U256::from(position.liquidity)
+        .checked_mul(U256::from(inside_b.wrapping_sub(position.fee_growth_inside_b_last_x64)))
+        .ok_or(ErrorCode::MathOverflow)?
+        >> 64;
+    position.tokens_owed_a = position
+        .tokens_owed_a
+        .checked_add(u64::try_from(earned_a).map_err(|_| ErrorCode::MathOverflow)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+    position.tokens_owed_b = position
+        .tokens_owed_b
+        .checked_add(u64::try_from(earned_b).map_err(|_| ErrorCode::MathOverflow)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+    position.fee_growth_inside_a_last_x64 = inside_a;
+    position.fee_growth_inside_b_last_x64 = inside_b;
+    position.liquidity =
+        add_liquidity_delta(position.liquidity, liquidity_delta).ok_or(ErrorCode::MathOverflow)?;
+
+    // Ticks no longer referenced by any position are cleared
+    if liquidity_delta < 0 {
+        if lower.liquidity_gross == 0 {
+            *lower = Tick::default();
+        }
+        if upper.liquidity_gross == 0 {
+            *upper = Tick::default();
+        }
+    }
+
+    Ok(())
+}
+
+// Applies a liquidity change to a position and returns the token amounts it moves
+fn modify_position(
+    pool: &mut Pool,
+    position: &mut Position,
+    tick_array_lower: &AccountLoader<TickArray>,
+    tick_array_upper: &AccountLoader<TickArray>,
+    liquidity_delta: i128,
+) -> Result<(u64, u64)> {
+    let tick_lower = position.tick_lower;
+    let tick_upper = position.tick_upper;
+
+    if tick_array_lower.key() == tick_array_upper.key() {
+        let mut tick_array = tick_array_lower.load_mut()?;
+        let lower_offset = tick_array
+            .tick_offset(tick_lower, pool.tick_spacing)
+            .ok_or(ErrorCode::InvalidTickArray)?;
+        let upper_offset = tick_array
+            .tick_offset(tick_upper, pool.tick_spacing)
+            .ok_or(ErrorCode::InvalidTickArray)?;
+        let (below, above) = tick_array.ticks.split_at_mut(upper_offset);
+        update_position_ticks(
+            pool,
+            position,
+            &mut below[lower_offset],
+            &mut above[0],
+            liquidity_delta,
+        )?;
+    } else {
+        let mut lower_array = tick_array_lower.load_mut()?;
+        let mut upper_array = tick_array_upper.load_mut()?;
+        let lower_offset = lower_array
+            .tick_offset(tick_lower, pool.tick_spacing)
+            .ok_or(ErrorCode::InvalidTickArray)?;
+        let upper_offset = upper_array
+            .tick_offset(tick_upper, pool.tick_spacing)
+            .ok_or(ErrorCode::InvalidTickArray)?;
+        update_position_ticks(
+            pool,
+            position,
+            &mut lower_array.ticks[lower_offset],
+            &mut upper_array.ticks[upper_offset],
+            liquidity_delta,
+        )?;
+    }
+
+    if liquidity_delta == 0 {
+        return Ok((0, 0));
+    }
+
+    // Deposits round in the pool's favor, withdrawals round down
+    let round_up = liquidity_delta > 0;
+    let liquidity = liquidity_delta.unsigned_abs();
+    let sqrt_price_lower = sqrt_price_at_tick(tick_lower).ok_or(ErrorCode::InvalidTickRange)?;
+    let sqrt_price_upper = sqrt_price_at_tick(tick_upper).ok_or(ErrorCode::InvalidTickRange)?;
+
+    let (amount_a, amount_b) = if pool.tick_current < tick_lower {
+        // Range above the price holds only token A
+        (
+            get_amount_a_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up),
+            Some(0),
+        )
+    } else if pool.tick_current < tick_upper {
+        // Range containing the price holds both tokens and is active
+        pool.liquidity =
+            add_liquidity_delta(pool.liquidity, liquidity_delta).ok_or(ErrorCode::MathOverflow)?;
+        (
+            get_amount_a_delta(pool.sqrt_price_x64, sqrt_price_upper, liquidity, round_up),
+            get_amount_b_delta(sqrt_price_lower, pool.sqrt_price_x64, liquidity, round_up),
+        )
+    } else {
+        // Range below the price holds only token B
+        (
+            Some(0),
+            get_amount_b_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up),
+        )
+    };
+
+    let amount_a = amount_a
+        .and_then(|amount| u64::try_from(amount).ok())
+        .ok_or(ErrorCode::MathOverflow)?;
+    let amount_b = amount_b
+        .and_then(|amount| u64::try_from(amount).ok())
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    Ok((amount_a, amount_b))
+}
+
+fn find_tick(
+    tick_arrays: &[RefMut<TickArray>],
+    tick_index: i32,
+    tick_spacing: u16,
+) -> Option<(usize, usize)> {
+    tick_arrays.iter().enumerate().find_map(|(array_index, tick_array)| {
+        tick_array
+            .tick_offset(tick_index, tick_spacing)
+            .map(|offset| (array_index, offset))
+    })
+}
+
+// Next tick to swap towards, searching at most one array's worth of ticks. Returns the
+// tick and whether it is initialized, or the last searched tick if none is.
+fn next_tick_in_arrays(
+    tick_arrays: &[RefMut<TickArray>],
+    tick_current: i32,
+    tick_spacing: u16,
+    a_to_b: bool,
+) -> Result<(i32, bool)> {
+    let spacing = tick_spacing as i32;
+    let mut candidate = tick_current.div_euclid(spacing) * spacing;
+    if !a_to_b {
+        candidate += spacing;
+    }
+
+    let mut last_searched = None;
+    for _ in 0..TICK_ARRAY_SIZE {
+        if candidate < MIN_TICK {
+            return Ok((MIN_TICK, false));
+        }
+        if candidate > MAX_TICK {
+            return Ok((MAX_TICK, false));
+        }
+
+        match find_tick(tick_arrays, candidate, tick_spacing) {
+            Some((array_index, offset)) => {
+                if tick_arrays[array_index].ticks[offset].liquidity_gross > 0 {
+                    return Ok((candidate, true));
+                }
+            }
+            None => break,
+        }
+
+        last_searched = Some(candidate);
+        candidate = if a_to_b { candidate - spacing } else { candidate + spacing };
+    }
+
+    last_searched
+        .map(|tick| (tick, false))
+        .ok_or_else(|| error!(ErrorCode::TickArrayMissing))
+}
+
+// Flips the fee growth outside a tick to the other side as the price crosses it and
+// returns the change in active liquidity for the swap direction
+fn cross_tick(
+    tick: &mut Tick,
+    fee_growth_global_a_x64: u128,
+    fee_growth_global_b_x64: u128,
+    a_to_b: bool,
+) -> i128 {
+    tick.fee_growth_outside_a_x64 =
+        fee_growth_global_a_x64.wrapping_sub(tick.fee_growth_outside_a_x64);
+    tick.fee_growth_outside_b_x64 =
+        fee_growth_global_b_x64.wrapping_sub(tick.fee_growth_outside_b_x64);
+    if a_to_b {
+        -tick.liquidity_net
+    } else {
+        tick.liquidity_net
+    }
+}
+
+// Executes an exact-input swap across initialized ticks and returns the output amount
+// and the fee charged
+fn swap_concentrated<'info>(
+    pool: &mut Pool,
+    pool_key: Pubkey,
+    tick_array_infos: &'info [AccountInfo<'info>],
+    amount_in: u64,
+    a_to_b: bool,
+) -> Result<(u64, u64)> {
+    let loaders = tick_array_infos
+        .iter()
+        .map(AccountLoader::<TickArray>::try_from)
+        .collect::<Result<Vec<_>>>()?;
+    let mut tick_arrays = Vec::with_capacity(loaders.len());
+    for loader in loaders.iter() {
+        let tick_array = loader.load_mut()?;
+        require!(tick_array.pool == pool_key, ErrorCode::InvalidTickArray);
+        tick_arrays.push(tick_array);
+    }
+    require!(!tick_arrays.is_empty(), ErrorCode::TickArrayMissing);
+
+    let sqrt_price_limit_x64 = if a_to_b { MIN_SQRT_PRICE_X64 } else { MAX_SQRT_PRICE_X64 };
+    let mut amount_remaining = amount_in as u128;
+    let mut amount_out: u128 = 0;
+    let mut fee_amount: u128 = 0;
+    let mut sqrt_price_x64 = pool.sqrt_price_x64;
+    let mut tick_current = pool.tick_current;
+    let mut liquidity = pool.liquidity;
+    let mut fee_growth_global_x64 = if a_to_b {
+        pool.fee_growth_global_a_x64
+    } else {
+        pool.fee_growth_global_b_x64
+    };
+
+    while amount_remaining > 0 && sqrt_price_x64 != sqrt_price_limit_x64 {
+        let (tick_next, initialized) =
+            next_tick_in_arrays(&tick_arrays, tick_current, pool.tick_spacing, a_to_b)?;
+        let sqrt_price_target_x64 = sqrt_price_at_tick(tick_next).ok_or(ErrorCode::MathOverflow)?;
+
+        let step = compute_swap_step(
+            sqrt_price_x64,
+            sqrt_price_target_x64,
+            liquidity,
+            amount_remaining,
+            pool.fee_numerator,
+            pool.fee_denominator,
+            a_to_b,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+
+        amount_remaining = amount_remaining
+            .checked_sub(step.amount_in)
+            .and_then(|amount| amount.checked_sub(step.fee_amount))
+            .ok_or(ErrorCode::MathOverflow)?;
+        amount_out = amount_out.checked_add(step.amount_out).ok_or(ErrorCode::MathOverflow)?;
+        fee_amount = fee_amount.checked_add(step.fee_amount).ok_or(ErrorCode::MathOverflow)?;
+
+        // Fees are shared by the liquidity active during this step
+        if liquidity > 0 {
+            fee_growth_global_x64 =
+                fee_growth_global_x64.wrapping_add((step.fee_amount << 64) / liquidity);
+        }
+
+        if step.sqrt_price_next_x64 == sqrt_price_target_x64 {
+            if initialized {
+                let (array_index, offset) = find_tick(&tick_arrays, tick_next, pool.tick_spacing)
+                    .ok_or(ErrorCode::TickArrayMissing)?;
+                let (global_a, global_b) = if a_to_b {
+                    (fee_growth_global_x64, pool.fee_growth_global_b_x64)
+                } else {
+                    (pool.fee_growth_global_a_x64, fee_growth_global_x64)
+                };
+                let liquidity_net = cross_tick(
+                    &mut tick_arrays[array_index].ticks[offset],
+                    global_a,
+                    global_b,
+                    a_to_b,
+                );
+                liquidity =
+                    add_liquidity_delta(liquidity, liquidity_net).ok_or(ErrorCode::MathOverflow)?;
+            }
+            tick_current = if a_to_b { tick_next - 1 } else { tick_next };
+        } else if step.sqrt_price_next_x64 != sqrt_price_x64 {
+            tick_current = tick_at_sqrt_price(step.sqrt_price_next_x64);
+        }
+        sqrt_price_x64 = step.sqrt_price_next_x64;
+    }
+
+    require!(amount_remaining == 0, ErrorCode::InsufficientLiquidity);
+
+    pool.sqrt_price_x64 = sqrt_price_x64;
+    pool.tick_current = tick_current;
+    pool.liquidity = liquidity;
+    if a_to_b {
+        pool.fee_growth_global_a_x64 = fee_growth_global_x64;
+    } else {
+        pool.fee_growth_global_b_x64 = fee_growth_global_x64;
+    }
+
+    Ok((
+        u64::try_from(amount_out).map_err(|_| error!(ErrorCode::MathOverflow))?,
+        u64::try_from(fee_amount).map_err(|_| error!(ErrorCode::MathOverflow))?,
+    ))
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Default)]
+pub enum PoolType {
+    #[default]
+    ConstantProduct,  // x * y = k
+    StableSwap,       // Curve-style StableSwap invariant
+    Concentrated,     // Liquidity concentrated in tick ranges owned by positions
+    Weighted,         // reserve_a^weight_a * reserve_b^weight_b = k
+    Pmm,              // Proactive market maker centered on an oracle price
+}
+
+// What guarded swaps do when the oracle is stale, unreadable or not confident enough
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Default)]
+pub enum OracleStalePolicy {
+    #[default]
+    FailOpen,   // Skip the deviation check
+    FailClosed, // Reject the swap
+}
+
+// What happens to a graduated launch pool's LP tokens
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Default)]
+pub enum LpDisposition {
+    #[default]
+    Burn,       // Burned, leaving only the minimum every pool locks
+    Lock,       // Held forever in the pool's locked LP account
+}
+
+// Account structures for the AMM pool
+#[account]
+#[derive(Default)]
+pub struct Pool {
+    pub token_a_mint: Pubkey,     // Mint address of token A
+    pub token_b_mint: Pubkey,     // Mint address of token B
+    pub token_a_account: Pubkey,  // Pool's token A account
+    pub token_b_account: Pubkey,  // Pool's token B account
+    pub lp_mint: Pubkey,          // LP token mint address
+    pub authority: Pubkey,        // Authority that can modify the pool
+    pub fee_numerator: u64,       // Numerator for fee calculation (e.g., 3 for 0.3%)
+    pub fee_denominator: u64,     // Denominator for fee calculation (e.g., 1000 for 0.3%)
+    pub fee_tier_bps: u16,        // Fee tier the pool was created under, part of its PDA seeds
+    pub dynamic_fee_enabled: bool, // Adds a volatility component to the swap fee
+    pub volatility_fee_factor_bps: u16, // Fee bps added per 10_000 bps of accumulated volatility
+    pub max_volatility_bps: u32,  // Cap on the volatility accumulator
+    pub max_dynamic_fee_bps: u16, // Cap on the total swap fee while dynamic fees are on
+    pub volatility_decay_period: i64, // Seconds for the volatility accumulator to decay to zero
+    pub volatility_bps: u64,      // Recent price movement accumulated by swaps, in bps
+    pub volatility_updated_ts: i64, // Timestamp the volatility accumulator was last updated
+    pub oracle_config: Pubkey,    // Oracle pricing A in B that guards swaps, default if none
+    pub max_oracle_deviation_bps: u16, // Furthest swaps may push the pool price from the oracle's
+    pub oracle_stale_policy: OracleStalePolicy, // Whether swaps proceed without an oracle price
+    pub max_price_impact_bps: u16, // Largest price move a single swap may cause, 0 for no limit
+    pub max_slot_price_move_bps: u16, // Largest move from the slot-start price, 0 for no limit
+    pub slot_start_price_x64: u128, // Token A price in token B as `slot_start_slot` began, Q64.64
+    pub slot_start_slot: u64,     // Slot the slot-start price was taken in
+    pub pool_type: PoolType,      // Invariant used to price swaps and deposits
+    pub initial_amp: u64,         // Amplification coefficient when the ramp started (stable pools)
+    pub target_amp: u64,          // Amplification coefficient once the ramp ends (stable pools)
+    pub weight_a: u64,            // Token A start weight, normalized to WEIGHT_ONE (weighted pools)
+    pub weight_b: u64,            // Token B start weight, normalized to WEIGHT_ONE (weighted pools)
+    pub end_weight_a: u64,        // Token A weight at the end of the schedule (weighted pools)
+    pub end_weight_b: u64,        // Token B weight at the end of the schedule (weighted pools)
+    pub weight_start_ts: i64,     // Timestamp weights start moving, zero for fixed weights
+    pub weight_end_ts: i64,       // Timestamp weights reach the end weights and the sale ends
+    pub liquidity_bootstrapping: bool, // Runs a bootstrapping sale until `weight_end_ts`
+    pub pmm_k: u64,               // Slippage parameter scaled by PMM_K_ONE (PMM pools)
+    pub pmm_price_x64: u128,      // Last oracle price of A in B, zero if unusable (PMM pools)
+    pub ramp_start_ts: i64,       // Timestamp the current amp ramp started
+    pub ramp_stop_ts: i64,        // Timestamp the current amp ramp finishes
+    pub price_a_cumulative_x64: u128, // Time-weighted sum of token A's price in token B, Q64.64
+    pub price_b_cumulative_x64: u128, // Time-weighted sum of token B's price in token A, Q64.64
+    pub last_observation_ts: i64, // Timestamp the price accumulators were last updated
+    pub tick_spacing: u16,        // Spacing between initializable ticks (concentrated pools)
+    pub tick_current: i32,        // Tick containing the current price (concentrated pools)
+    pub sqrt_price_x64: u128,     // Current sqrt price as Q64.64 (concentrated pools)
+    pub liquidity: u128,          // Liquidity active at the current tick (concentrated pools)
+    pub fee_growth_global_a_x64: u128, // Token A fees earned per unit of liquidity, Q64.64
+    pub fee_growth_global_b_x64: u128, // Token B fees earned per unit of liquidity, Q64.64
+    pub reserve_a: u64,           // Token A reserves, only changed by pool instructions
+    pub reserve_b: u64,           // Token B reserves, only changed by pool instructions
+    pub min_initial_deposit_a: u64, // Smallest token A amount accepted for the first deposit
+    pub min_initial_deposit_b: u64, // Smallest token B amount accepted for the first deposit
+    pub locked: bool,             // Set while a flash loan is outstanding
+    pub paused: bool,             // Blocks swaps and deposits, withdrawals stay open
+    pub pending_authority: Pubkey, // Authority proposed by the current one, default if none
+    pub bump: u8,                 // PDA bump seed
+    pub authority_bump: u8,       // Bump seed of the pool authority PDA
+}
+
+impl Pool {
+    // Amplification coefficient linearly interpolated along the current ramp
+    pub fn current_amp(&self, now: i64) -> u64 {
+        if now >= self.ramp_stop_ts || self.ramp_stop_ts <= self.ramp_start_ts {
+            return self.target_amp;
+        }
+
+        let elapsed = now.saturating_sub(self.ramp_start_ts).max(0) as u128;
+        let duration = (self.ramp_stop_ts - self.ramp_start_ts) as u128;
+        if self.target_amp > self.initial_amp {
+            let delta = (self.target_amp - self.initial_amp) as u128;
+            self.initial_amp + (delta * elapsed / duration) as u64
+        } else {
+            let delta = (self.initial_amp - self.target_amp) as u128;
+            self.initial_amp - (delta * elapsed / duration) as u64
+        }
+    }
+
+    // Weights linearly interpolated along the weight schedule, held at either end outside of it
+    pub fn current_weights(&self, now: i64) -> (u64, u64) {
+        if self.weight_end_ts <= self.weight_start_ts || now <= self.weight_start_ts {
+            return (self.weight_a, self.weight_b);
+        }
+        if now >= self.weight_end_ts {
+            return (self.end_weight_a, self.end_weight_b);
+        }
+
+        let elapsed = (now - self.weight_start_ts) as u128;
+        let duration = (self.weight_end_ts - self.weight_start_ts) as u128;
+        let weight_a = if self.end_weight_a > self.weight_a {
+            let delta = (self.end_weight_a - self.weight_a) as u128;
+            self.weight_a + (delta * elapsed / duration) as u64
+        } else {
+            let delta = (self.weight_a - self.end_weight_a) as u128;
+            self.weight_a - (delta * elapsed / duration) as u64
+        };
+        (weight_a, WEIGHT_ONE - weight_a)
+    }
+
+    // Normalized weights of the input and output token for a swap in the given direction
+    pub fn swap_weights(&self, a_to_b: bool, now: i64) -> (u64, u64) {
+        let (weight_a, weight_b) = self.current_weights(now);
+        if a_to_b {
+            (weight_a, weight_b)
+        } else {
+            (weight_b, weight_a)
+        }
+    }
+
+    // Whether the pool is a liquidity bootstrapping pool whose sale has not ended yet
+    pub fn sale_in_progress(&self, now: i64) -> bool {
+        self.liquidity_bootstrapping && now < self.weight_end_ts
+    }
+
+    // Volatility accumulator decayed linearly to zero over the decay period
+    pub fn volatility_at(&self, now: i64) -> u64 {
+        let elapsed = now.saturating_sub(self.volatility_updated_ts).max(0);
+        if elapsed >= self.volatility_decay_period {
+            return 0;
+        }
+
+        let remaining = (self.volatility_decay_period - elapsed) as u128;
+        (self.volatility_bps as u128 * remaining / self.volatility_decay_period as u128) as u64
+    }
+
+    // Swap fee numerator, the base fee plus the volatility component when dynamic fees are on
+    pub fn swap_fee_numerator(&self, now: i64) -> u64 {
+        if !self.dynamic_fee_enabled {
+            return self.fee_numerator;
+        }
+
+        let fee_denominator = self.fee_denominator as u128;
+        let variable_fee_bps = self.volatility_at(now) as u128
+            * self.volatility_fee_factor_bps as u128
+            / FEE_TIER_DENOMINATOR as u128;
+        let variable_fee = variable_fee_bps * fee_denominator / FEE_TIER_DENOMINATOR as u128;
+        let max_fee =
+            self.max_dynamic_fee_bps as u128 * fee_denominator / FEE_TIER_DENOMINATOR as u128;
+        (self.fee_numerator as u128 + variable_fee)
+            .min(max_fee)
+            .max(self.fee_numerator as u128) as u64
+    }
+
+    // Adds the price move from the tracked reserves to `reserves_after` to the decayed volatility
+    pub fn record_volatility(&mut self, reserves_after: (u64, u64), now: i64) -> Option<()> {
+        if !self.dynamic_fee_enabled {
+            return Some(());
+        }
+
+        let price_move_bps =
+            price_change_bps(self, (self.reserve_a, self.reserve_b), reserves_after, now)?;
+        self.volatility_bps = self
+            .volatility_at(now)
+            .saturating_add(price_move_bps)
+            .min(self.max_volatility_bps as u64);
+        self.volatility_updated_ts = now;
+
+        Some(())
+    }
+
+    // Rejects a swap whose own price impact, or whose distance from the price the pool started
+    // the slot at, is over the configured limit. The first swap in a slot records its start price
+    pub fn check_circuit_breaker(
+        &mut self,
+        price_before_x64: Option<u128>,
+        price_after_x64: Option<u128>,
+        slot: u64,
+    ) -> Result<()> {
+        let (price_before_x64, price_after_x64) = match (price_before_x64, price_after_x64) {
+            (Some(price_before_x64), Some(price_after_x64)) if price_before_x64 > 0 => {
+                (price_before_x64, price_after_x64)
+            }
+            // Empty pools have no price to move
+            _ => return Ok(()),
+        };
+        if self.slot_start_slot != slot || self.slot_start_price_x64 == 0 {
+            self.slot_start_slot = slot;
+            self.slot_start_price_x64 = price_before_x64;
+        }
+
+        if self.max_price_impact_bps > 0 {
+            let impact_bps =
+                relative_change_bps(price_before_x64, price_after_x64).unwrap_or(u64::MAX);
+            require!(
+                impact_bps <= self.max_price_impact_bps as u64,
+                ErrorCode::PriceImpactLimitExceeded
+            );
+        }
+        if self.max_slot_price_move_bps > 0 {
+            let slot_move_bps =
+                relative_change_bps(self.slot_start_price_x64, price_after_x64).unwrap_or(u64::MAX);
+            require!(
+                slot_move_bps <= self.max_slot_price_move_bps as u64,
+                ErrorCode::SlotPriceMoveLimitExceeded
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct Observation {
+    pub timestamp: i64,                    // Time the observation was written
+    pub price_a_cumulative_x64: u128,      // Pool's token A price accumulator at that time
+    pub price_b_cumulative_x64: u128,      // Pool's token B price accumulator at that time
+}
+
+#[account]
+pub struct ObservationBuffer {
+    pub pool: Pubkey,                      // Pool the observations belong to
+    pub capacity: u16,                     // Maximum number of observations kept
+    pub next_index: u16,                   // Slot the next observation is written to
+    pub observations: Vec<Observation>,    // Ring buffer of observations
+    pub bump: u8,                          // PDA bump seed
+}
+
+impl ObservationBuffer {
+    pub fn space(capacity: u16) -> usize {
+        8 + 32 + 2 + 2 + 4 + capacity as usize * std::mem::size_of::<Observation>() + 1
+    }
+
+    // Writes at most one observation per timestamp, overwriting the oldest when full
+    pub fn record(
+        &mut self,
+        timestamp: i64,
+        price_a_cumulative_x64: u128,
+        price_b_cumulative_x64: u128,
+    ) {
+        if let Some(latest) = self.latest() {
+            if latest.timestamp >= timestamp {
+                return;
+            }
+        }
+
+        let observation = Observation {
+            timestamp,
+            price_a_cumulative_x64,
+            price_b_cumulative_x64,
+        };
+        if self.observations.len() < self.capacity as usize {
+            self.observations.push(observation);
+        } else {
+            self.observations[self.next_index as usize] = observation;
+        }
+        self.next_index = (self.next_index + 1) % self.capacity;
+    }
+
+    pub fn latest(&self) -> Option<&Observation> {
+        if self.observations.is_empty() {
+            return None;
+        }
+        let capacity = self.capacity as usize;
+        let index = (self.next_index as usize + capacity - 1) % capacity;
+        self.observations.get(index)
+    }
+
+    // Observations from oldest to newest
+    pub fn chronological(&self) -> impl Iterator<Item = &Observation> {
+        let len = self.observations.len();
+        let start = if len < self.capacity as usize { 0 } else { self.next_index as usize };
+        (0..len).map(move |i| &self.observations[(start + i) % len])
+    }
+
+    // Accumulator values at `target_ts`, interpolated between the surrounding observations.
+    // The newest point is the live accumulator value at `now`.
+    pub fn cumulative_prices_at(
+        &self,
+        target_ts: i64,
+        now: i64,
+        price_a_cumulative_now: u128,
+        price_b_cumulative_now: u128,
+    ) -> Option<(u128, u128)> {
+        let live = Observation {
+            timestamp: now,
+            price_a_cumulative_x64: price_a_cumulative_now,
+            price_b_cumulative_x64: price_b_cumulative_now,
+        };
+
+        let mut before: Option<&Observation> = None;
+        for observation in self.chronological().chain(std::iter::once(&live)) {
+            if observation.timestamp == target_ts {
+                return Some((
+                    observation.price_a_cumulative_x64,
+                    observation.price_b_cumulative_x64,
+                ));
+            }
+            if observation.timestamp > target_ts {
+                // Interpolate linearly, requires an observation at or before the target
+                let before = before?;
+                let span = (observation.timestamp - before.timestamp) as u128;
+                let offset = (target_ts - before.timestamp) as u128;
+                let interpolate = |start: u128, end: u128| {
+                    let delta =
+                        U256::from(end.wrapping_sub(start)) * U256::from(offset) / U256::from(span);
+                    start.wrapping_add(delta.low_u128())
+                };
+                return Some((
+                    interpolate(before.price_a_cumulative_x64, observation.price_a_cumulative_x64),
+                    interpolate(before.price_b_cumulative_x64, observation.price_b_cumulative_x64),
+                ));
+            }
+            before = Some(observation);
+        }
+
+        None
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TwapPrice {
+    pub price_a_x64: u128,                 // Time-weighted price of token A in token B, Q64.64
+    pub price_b_x64: u128,                 // Time-weighted price of token B in token A, Q64.64
+    pub seconds_ago: u32,                  // Length of the averaging window
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SwapQuote {
+    pub amount_in: u64,                    // Amount sent by the user
+    pub amount_out: u64,                   // Amount the user receives, after any transfer fee
+    pub fee: u64,                          // Swap fee charged in the input token
+    pub price_impact_bps: u64,             // Execution price shortfall against the spot price
+    pub reserve_a: u64,                    // Token A reserve after the swap
+    pub reserve_b: u64,                    // Token B reserve after the swap
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LiquidityQuote {
+    pub amount_a: u64,                     // Token A deposited into or received from the pool
+    pub amount_b: u64,                     // Token B deposited into or received from the pool
+    pub lp_tokens: u64,                    // LP tokens minted or burned
+    pub fee: u64,                          // Imbalance fee in LP tokens (stable pool deposits)
+    pub price_impact_bps: u64,             // Move of the spot price caused by the change
+    pub reserve_a: u64,                    // Token A reserve after the change
+    pub reserve_b: u64,                    // Token B reserve after the change
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LiquidityBootstrappingParams {
+    pub start_weight_a: u64,               // Token A weight when the sale starts
+    pub start_weight_b: u64,               // Token B weight when the sale starts
+    pub end_weight_a: u64,                 // Token A weight when the sale ends
+    pub end_weight_b: u64,                 // Token B weight when the sale ends
+    pub start_ts: i64,                     // Timestamp weights start moving
+    pub end_ts: i64,                       // Timestamp the sale ends
+    pub min_initial_deposit_a: u64,        // Smallest token A amount accepted for the first deposit
+    pub min_initial_deposit_b: u64,        // Smallest token B amount accepted for the first deposit
+}
+
+#[zero_copy]
+#[derive(Default)]
+pub struct Tick {
+    pub liquidity_net: i128,               // Liquidity added when the tick is crossed upwards
+    pub liquidity_gross: u128,             // Total liquidity referencing this tick
+    pub fee_growth_outside_a_x64: u128,    // Token A fee growth on the other side of the tick
+    pub fee_growth_outside_b_x64: u128,    // Token B fee growth on the other side of the tick
+}
+
+#[account(zero_copy)]
+pub struct TickArray {
+    pub pool: Pubkey,                      // Concentrated pool the ticks belong to
+    pub start_tick_index: i32,             // Tick index of the first entry
+    pub ticks: [Tick; TICK_ARRAY_SIZE],    // Ticks spaced by the pool's tick spacing
+}
+
+impl TickArray {
+    // Offset of `tick_index` in this array, if the array covers it
+    pub fn tick_offset(&self, tick_index: i32, tick_spacing: u16) -> Option<usize> {
+        let spacing = tick_spacing as i32;
+        let start_tick_index = self.start_tick_index;
+        if tick_index % spacing != 0 || tick_index < start_tick_index {
+            return None;
+        }
+
+        let offset = ((tick_index - start_tick_index) / spacing) as usize;
+        if offset < TICK_ARRAY_SIZE {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+}
+
+#[account]
+pub struct Position {
+    pub pool: Pubkey,                      // Concentrated pool the position provides liquidity to
+    pub owner: Pubkey,                     // Owner allowed to modify the position
+    pub tick_lower: i32,                   // Lower tick of the range (inclusive)
+    pub tick_upper: i32,                   // Upper tick of the range (exclusive)
+    pub liquidity: u128,                   // Liquidity provided across the range
+    pub fee_growth_inside_a_last_x64: u128, // Token A fee growth inside the range at last update
+    pub fee_growth_inside_b_last_x64: u128, // Token B fee growth inside the range at last update
+    pub tokens_owed_a: u64,                // Token A fees owed to the position
+    pub tokens_owed_b: u64,                // Token B fees owed to the position
+    pub bump: u8,                          // PDA bump seed
+}
+
+#[account]
+pub struct FeeTierRegistry {
+    pub authority: Pubkey,                 // Protocol authority that manages the fee tiers
+    pub fee_tiers: Vec<u16>,               // Enabled pool fees in bps, sorted ascending
+    pub bump: u8,                          // PDA bump seed
+}
+
+impl FeeTierRegistry {
+    pub const SPACE: usize = 8 + 32 + 4 + 2 * MAX_FEE_TIERS + 1;
+}
+
+#[account]
+pub struct PoolRegistry {
+    pub pool_count: u64,                   // Pools registered across all pages
+    pub page_count: u32,                   // Pages created, indexed from zero
+    pub bump: u8,                          // PDA bump seed
+}
+
+#[account]
+pub struct PoolRegistryPage {
+    pub index: u32,                        // Position of the page in the registry
+    pub pools: Vec<Pubkey>,                // Pools in creation order
+    pub bump: u8,                          // PDA bump seed
+}
+
+impl PoolRegistryPage {
+    pub const SPACE: usize = 8 + 4 + 4 + 32 * POOLS_PER_REGISTRY_PAGE + 1;
+}
+
+// Referral payouts to one referrer in one token, amounts are in that token
+#[account]
+pub struct ReferrerStats {
+    pub referrer: Pubkey,                  // Wallet that owns the referrer token accounts
+    pub mint: Pubkey,                      // Token the payouts are made in
+    pub swap_count: u64,                   // Swaps referred
+    pub volume: u128,                      // Swap input received by pools on referred swaps
+    pub fees_earned: u128,                 // Referral fees paid out
+    pub bump: u8,                          // PDA bump seed
+}
+
+// Pool over up to MAX_MULTI_POOL_TOKENS tokens sharing one LP mint. Member arrays are in
+// ascending mint order and only the first `token_count` entries are used
+#[account]
+#[derive(Default)]
+pub struct MultiPool {
+    pub authority: Pubkey,                 // Creator, the only account that may open member vaults
+    pub lp_mint: Pubkey,                   // LP token mint
+    pub pool_type: PoolType,               // ConstantProduct or StableSwap
+    pub amp: u64,                          // Amplification coefficient (stable pools)
+    pub fee_numerator: u64,                // Swap fee numerator
+    pub fee_denominator: u64,              // Swap fee denominator
+    pub fee_tier_bps: u16,                 // Fee tier the pool was created at
+    pub token_count: u8,                   // Number of member tokens
+    pub mints: [Pubkey; MAX_MULTI_POOL_TOKENS],  // Member mints
+    pub vaults: [Pubkey; MAX_MULTI_POOL_TOKENS], // Member vaults, default until opened
+    pub reserves: [u64; MAX_MULTI_POOL_TOKENS],  // Member reserves, changed only by the pool
+    pub authority_bump: u8,                // Bump seed of the pool authority PDA
+}
+
+impl MultiPool {
+    // Reserves of the pool's members, in mint order
+    pub fn active_reserves(&self) -> &[u64] {
+        &self.reserves[..self.token_count as usize]
+    }
+
+    // Position of the member held in `vault`
+    pub fn member_index(&self, vault: Pubkey) -> Option<usize> {
+        if vault == Pubkey::default() {
+            return None;
+        }
+        self.vaults[..self.token_count as usize]
+            .iter()
+            .position(|member_vault| *member_vault == vault)
+    }
+
+    // Whether every member vault has been opened
+    pub fn is_ready(&self) -> bool {
+        self.vaults[..self.token_count as usize]
+            .iter()
+            .all(|vault| *vault != Pubkey::default())
+    }
+}
+
+// Sale of a newly launched token against a virtual-reserve bonding curve. The buy that
+// takes the curve's market cap to the threshold stops trading and migrates the reserves
+// into a constant product pool
+#[account]
+#[derive(Default)]
+pub struct LaunchPool {
+    pub creator: Pubkey,                   // Account that deposited the tokens for sale
+    pub token_mint: Pubkey,                // Token being launched
+    pub quote_mint: Pubkey,                // Token the launch is priced and bought in
+    pub token_vault: Pubkey,               // Vault holding the unsold tokens
+    pub quote_vault: Pubkey,               // Vault holding the quote raised
+    pub token_supply: u64,                 // Mint supply at creation, used for the market cap
+    pub virtual_token_reserve: u64,        // Curve token reserve, real plus any virtual offset
+    pub virtual_quote_reserve: u64,        // Curve quote reserve, virtual liquidity plus net buys
+    pub token_reserve: u64,                // Tokens left for sale
+    pub quote_reserve: u64,                // Quote raised, including trading fees
+    pub graduation_market_cap: u64,        // Market cap in quote units at which the curve closes
+    pub fee_tier_bps: u16,                 // Trading fee, also the graduated pool's fee tier
+    pub lp_disposition: LpDisposition,     // Whether the graduated pool's LP is burned or locked
+    pub complete: bool,                    // Reserves migrated at the threshold, trading stopped
+    pub pool: Pubkey,                      // Constant product pool the reserves migrate into
+    pub bump: u8,                          // PDA bump seed
+    pub authority_bump: u8,                // Bump seed of the launch authority PDA
+}
+
+impl LaunchPool {
+    // Market cap in quote units at the curve's current price
+    pub fn market_cap(&self) -> Option<u128> {
+        (self.virtual_quote_reserve as u128)
+            .checked_mul(self.token_supply as u128)?
+            .checked_div(self.virtual_token_reserve as u128)
+    }
+
+    // Highest market cap a single buy can reach without taking more than the deposit. The
+    // curve keeps its virtual tokens beyond the deposit, so the quote reserve can grow
+    // until ceil(k / quote) falls to them
+    pub fn max_market_cap(&self) -> Option<u128> {
+        let product = (self.virtual_quote_reserve as u128)
+            .checked_mul(self.virtual_token_reserve as u128)?;
+        let token_floor = self.virtual_token_reserve.checked_sub(self.token_reserve)? as u128;
+        let quote_reserve = if token_floor > 1 {
+            (product.checked_sub(1)? / (token_floor - 1)).min(u64::MAX as u128)
+        } else {
+            u64::MAX as u128
+        };
+        let token_reserve = product
+            .checked_add(quote_reserve.checked_sub(1)?)?
+            .checked_div(quote_reserve)?;
+        quote_reserve.checked_mul(self.token_supply as u128)?.checked_div(token_reserve)
+    }
+}
+
+// Events for indexers, reserves are the pool's tracked reserves after the change
+#[event]
+pub struct PoolInitialized {
+    pub pool: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub authority: Pubkey,
+    pub pool_type: PoolType,
+    pub fee_tier_bps: u16,
+}
+
+#[event]
+pub struct LiquidityAdded {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub lp_tokens: u64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[event]
+pub struct LiquidityRemoved {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub lp_tokens: u64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[event]
+pub struct SwapExecuted {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    pub amount_in: u64,           // Amount received by the pool, after any transfer fee
+    pub amount_out: u64,          // Amount sent by the pool, before any transfer fee
+    pub fee: u64,                 // Swap fee charged in the input token
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[event]
+pub struct ReservesSynced {
+    pub pool: Pubkey,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[event]
+pub struct ExcessSkimmed {
+    pub pool: Pubkey,
+    pub recipient_token_a: Pubkey,
+    pub recipient_token_b: Pubkey,
+    pub amount_a: u64,            // Untracked token A sent by the pool, before any transfer fee
+    pub amount_b: u64,            // Untracked token B sent by the pool, before any transfer fee
+}
+
+#[event]
+pub struct FlashLoanExecuted {
+    pub pool: Pubkey,
+    pub borrower: Pubkey,
+    pub amount_a: u64,            // Token A lent, before any transfer fee
+    pub amount_b: u64,            // Token B lent, before any transfer fee
+    pub fee_a: u64,               // Flash fee added to the token A reserve
+    pub fee_b: u64,               // Flash fee added to the token B reserve
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[event]
+pub struct PositionLiquidityIncreased {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub liquidity_delta: u128,
+    pub amount_a: u64,            // Amount sent by the owner, including any transfer fee
+    pub amount_b: u64,            // Amount sent by the owner, including any transfer fee
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[event]
+pub struct PositionLiquidityDecreased {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub liquidity_delta: u128,
+    pub amount_a: u64,            // Amount sent by the pool, before any transfer fee
+    pub amount_b: u64,            // Amount sent by the pool, before any transfer fee
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[event]
+pub struct PositionFeesCollected {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub amount_a: u64,            // Fees sent by the pool, before any transfer fee
+    pub amount_b: u64,            // Fees sent by the pool, before any transfer fee
+}
+
+#[event]
+pub struct MultiPoolSwapExecuted {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub mint_in: Pubkey,
+    pub mint_out: Pubkey,
+    pub amount_in: u64,           // Amount received by the pool, after any transfer fee
+    pub amount_out: u64,          // Amount sent by the pool, before any transfer fee
+    pub fee: u64,                 // Swap fee charged in the input token
+    pub reserves: Vec<u64>,       // Member reserves in mint order
+}
+
+#[event]
+pub struct MultiPoolLiquidityAdded {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amounts: Vec<u64>,        // Amount of each member received, in mint order
+    pub lp_tokens: u64,
+    pub reserves: Vec<u64>,
+}
+
+#[event]
+pub struct MultiPoolLiquidityRemoved {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amounts: Vec<u64>,        // Amount of each member paid out, in mint order
+    pub lp_tokens: u64,
+    pub reserves: Vec<u64>,
+}
+
+#[event]
+pub struct LaunchPoolCreated {
+    pub launch_pool: Pubkey,
+    pub creator: Pubkey,
+    pub token_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub token_amount: u64,        // Tokens for sale received by the vault
+    pub virtual_token_reserve: u64,
+    pub virtual_quote_reserve: u64,
+    pub graduation_market_cap: u64,
+}
+
+#[event]
+pub struct LaunchTradeExecuted {
+    pub launch_pool: Pubkey,
+    pub user: Pubkey,
+    pub is_buy: bool,
+    pub quote_amount: u64,        // Quote received on buys or paid out on sells
+    pub token_amount: u64,        // Tokens paid out on buys or received on sells
+    pub fee: u64,                 // Trading fee charged in the quote token
+    pub virtual_quote_reserve: u64,
+    pub virtual_token_reserve: u64,
+    pub complete: bool,           // Whether this trade closed the curve
+}
+
+#[event]
+pub struct LaunchPoolGraduated {
+    pub launch_pool: Pubkey,
+    pub pool: Pubkey,
+    pub token_amount: u64,        // Tokens migrated into the pool
+    pub quote_amount: u64,        // Quote migrated into the pool
+    pub tokens_burned: u64,       // Unsold tokens burned to keep the price continuous
+    pub lp_tokens: u64,           // LP tokens minted to the locked account
+    pub lp_burned: u64,           // Of which burned
+}
+
+// Context for initializing a new pool
+#[derive(Accounts)]
+#[instruction(fee_tier_bps: u16)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<Pool>(),
+        seeds = [
+            b"pool".as_ref(),
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref(),
+            fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is a PDA used as the authority for the pool's token accounts
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"fee_tier_registry".as_ref()],
+        bump = fee_tier_registry.bump,
+    )]
+    pub fee_tier_registry: Account<'info, FeeTierRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_registry".as_ref()],
+        bump = pool_registry.bump,
+    )]
+    pub pool_registry: Account<'info, PoolRegistry>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool_registry_page".as_ref(),
+            pool_registry_page.index.to_le_bytes().as_ref(),
+        ],
+        bump = pool_registry_page.bump,
+    )]
+    pub pool_registry_page: Account<'info, PoolRegistryPage>,
+
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = token_a_mint,
+        token::authority = pool_authority,
+        token::token_program = token_a_program,
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = token_b_mint,
+        token::authority = pool_authority,
+        token::token_program = token_b_program,
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = 9,
+        mint::authority = pool_authority,
+        mint::token_program = token_program,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"locked_lp".as_ref(), pool.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = pool_authority,
+        token::token_program = token_program,
+    )]
+    pub locked_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Context for adding liquidity to a pool
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    // Either user token account may be left out on the native SOL side of the pool
+    #[account(
+        mut,
+        constraint = user_token_a.mint == pool.token_a_mint,
+        constraint = user_token_a.owner == user.key()
+    )]
+    pub user_token_a: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_token_b.mint == pool.token_b_mint,
+        constraint = user_token_b.owner == user.key()
+    )]
+    pub user_token_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token.mint == pool.lp_mint,
+        constraint = user_lp_token.owner == user.key()
+    )]
+    pub user_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"locked_lp".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    pub locked_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"wsol".as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: Temporary wSOL account, created and closed within the instruction
+    pub wsol_account: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        mut,
+        seeds = [b"observations".as_ref(), pool.key().as_ref()],
+        bump = observation_buffer.bump,
+    )]
+    pub observation_buffer: Option<Account<'info, ObservationBuffer>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    pub system_program: Option<Program<'info, System>>,
+}
+
+// Context for removing liquidity from a pool
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_a.mint == pool.token_a_mint,
+        constraint = user_token_a.owner == user.key()
+    )]
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_b.mint == pool.token_b_mint,
+        constraint = user_token_b.owner == user.key()
+    )]
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token.mint == pool.lp_mint,
+        constraint = user_lp_token.owner == user.key()
+    )]
+    pub user_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"observations".as_ref(), pool.key().as_ref()],
+        bump = observation_buffer.bump,
+    )]
+    pub observation_buffer: Option<Account<'info, ObservationBuffer>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+// Context for depositing a single token into a pool
+#[derive(Accounts)]
+pub struct AddLiquiditySingleSided<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = (user_token_in.mint == pool.token_a_mint || user_token_in.mint == pool.token_b_mint),
+        constraint = user_token_in.owner == user.key()
+    )]
+    pub user_token_in: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token.mint == pool.lp_mint,
+        constraint = user_lp_token.owner == user.key()
+    )]
+    pub user_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"observations".as_ref(), pool.key().as_ref()],
+        bump = observation_buffer.bump,
+    )]
+    pub observation_buffer: Option<Account<'info, ObservationBuffer>>,
+
+    // Required when the pool is guarded by an oracle
+    pub oracle_config: Option<Account<'info, OracleConfig>>,
+
+    /// CHECK: Validated against the oracle config when the guard reads it
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+// Context for withdrawing a single token from a pool
+#[derive(Accounts)]
+pub struct RemoveLiquiditySingleSided<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        constraint = (user_token_out.mint == pool.token_a_mint || user_token_out.mint == pool.token_b_mint),
+        constraint = user_token_out.owner == user.key()
+    )]
+    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = user_lp_token.mint == pool.lp_mint,
+        constraint = user_lp_token.owner == user.key()
+    )]
+    pub user_lp_token: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"observations".as_ref(), pool.key().as_ref()],
+        bump = observation_buffer.bump,
+    )]
+    pub observation_buffer: Option<Account<'info, ObservationBuffer>>,
+
+    // Required when the pool is guarded by an oracle
+    pub oracle_config: Option<Account<'info, OracleConfig>>,
+
+    /// CHECK: Validated against the oracle config when the guard reads it
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+// Context for swapping tokens
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+    
+    // Either user token account may be left out on the native SOL side of the pool
+    #[account(
+        mut,
+        constraint = (user_token_in.mint == pool.token_a_mint || user_token_in.mint == pool.token_b_mint),
+        constraint = user_token_in.owner == user.key()
+    )]
+    pub user_token_in: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        constraint = (user_token_out.mint == pool.token_a_mint || user_token_out.mint == pool.token_b_mint),
+        constraint = user_token_out.owner == user.key()
+    )]
+    pub user_token_out: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"wsol".as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: Temporary wSOL account, created and closed within the instruction
+    pub wsol_account: Option<UncheckedAccount<'info>>,
+
+    // Referrer paid a share of the fee in the input token, with their stats for that token
+    #[account(mut)]
+    pub referrer_token: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [
+            b"referrer_stats".as_ref(),
+            referrer_stats.referrer.as_ref(),
+            referrer_stats.mint.as_ref(),
+        ],
+        bump = referrer_stats.bump,
+    )]
+    pub referrer_stats: Option<Account<'info, ReferrerStats>>,
+    
+    // Protocol fee settings holding the referral share, required with a referrer
+    #[account(
+        seeds = [b"fee_config".as_ref()],
+        bump = fee_config.bump,
+        seeds::program = openfund_fee_management::ID,
+    )]
+    pub fee_config: Option<Account<'info, FeeConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"observations".as_ref(), pool.key().as_ref()],
+        bump = observation_buffer.bump,
+    )]
+    pub observation_buffer: Option<Account<'info, ObservationBuffer>>,
+
+    // Required when the pool is guarded by an oracle
+    pub oracle_config: Option<Account<'info, OracleConfig>>,
+
+    /// CHECK: Validated against the oracle config when the guard reads it
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+    pub system_program: Option<Program<'info, System>>,
+}
+
+// Context for swapping along a route of pools passed as remaining accounts
+#[derive(Accounts)]
+pub struct RouteSwap<'info> {
+    #[account(
+        mut,
+        constraint = user_token_in.owner == user.key()
+    )]
+    pub user_token_in: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_out.owner == user.key()
+    )]
+    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+// Context for creating the observation buffer of a pool
+#[derive(Accounts)]
+#[instruction(capacity: u16)]
+pub struct InitializeObservationBuffer<'info> {
+    #[account(
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = ObservationBuffer::space(capacity),
+        seeds = [b"observations".as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub observation_buffer: Account<'info, ObservationBuffer>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Context for reading a pool's time-weighted average price
+#[derive(Accounts)]
+pub struct Observe<'info> {
+    #[account(
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"observations".as_ref(), pool.key().as_ref()],
+        bump = observation_buffer.bump,
+    )]
+    pub observation_buffer: Account<'info, ObservationBuffer>,
+}
+
+// Context for quoting a swap without moving tokens
+#[derive(Accounts)]
+pub struct QuoteSwap<'info> {
+    #[account(
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+}
+
+// Context for quoting a deposit without moving tokens
+#[derive(Accounts)]
+pub struct QuoteAddLiquidity<'info> {
+    #[account(
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+}
+
+// Context for quoting a withdrawal without moving tokens
+#[derive(Accounts)]
+pub struct QuoteRemoveLiquidity<'info> {
+    #[account(
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+}
+
+// Context for borrowing pool reserves within a single instruction
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = borrower_token_a.mint == pool.token_a_mint
+    )]
+    pub borrower_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = borrower_token_b.mint == pool.token_b_mint
+    )]
+    pub borrower_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(executable)]
+    /// CHECK: This is the program called back while the loan is outstanding
+    pub borrower_program: AccountInfo<'info>,
+
+    pub borrower: Signer<'info>,
+
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+// Context for sending vault balances above the tracked reserves to a recipient
+#[derive(Accounts)]
+pub struct Skim<'info> {
+    #[account(
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        constraint = recipient_token_a.mint == pool.token_a_mint
+    )]
+    pub recipient_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = recipient_token_b.mint == pool.token_b_mint
+    )]
+    pub recipient_token_b: InterfaceAccount<'info, TokenAccount>,
+    
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+// Context for resetting the tracked reserves to the vault balances
+#[derive(Accounts)]
+pub struct Sync<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for ramping the amplification coefficient of a stable pool
+#[derive(Accounts)]
+pub struct RampAmp<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for stopping an amplification ramp
+#[derive(Accounts)]
+pub struct StopRampAmp<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for creating a tick array of a concentrated pool
+#[derive(Accounts)]
+#[instruction(start_tick_index: i32)]
+pub struct InitializeTickArray<'info> {
+    #[account(
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<TickArray>(),
+        seeds = [
+            b"tick_array".as_ref(),
+            pool.key().as_ref(),
+            start_tick_index.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub tick_array: AccountLoader<'info, TickArray>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Context for opening a concentrated liquidity position
+#[derive(Accounts)]
+#[instruction(tick_lower: i32, tick_upper: i32)]
+pub struct OpenPosition<'info> {
+    #[account(
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + std::mem::size_of::<Position>(),
+        seeds = [
+            b"position".as_ref(),
+            pool.key().as_ref(),
+            owner.key().as_ref(),
+            tick_lower.to_le_bytes().as_ref(),
+            tick_upper.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Context for adding liquidity to a concentrated position
+#[derive(Accounts)]
+pub struct IncreaseLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = position.pool == pool.key(),
+        constraint = position.owner == owner.key()
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(
+        mut,
+        constraint = tick_array_lower.load()?.pool == pool.key()
+    )]
+    pub tick_array_lower: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        constraint = tick_array_upper.load()?.pool == pool.key()
+    )]
+    pub tick_array_upper: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_a.mint == pool.token_a_mint,
+        constraint = user_token_a.owner == owner.key()
+    )]
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_b.mint == pool.token_b_mint,
+        constraint = user_token_b.owner == owner.key()
+    )]
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+// Context for removing liquidity from a concentrated position
+#[derive(Accounts)]
+pub struct DecreaseLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = position.pool == pool.key(),
+        constraint = position.owner == owner.key()
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(
+        mut,
+        constraint = tick_array_lower.load()?.pool == pool.key()
+    )]
+    pub tick_array_lower: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        constraint = tick_array_upper.load()?.pool == pool.key()
+    )]
+    pub tick_array_upper: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_a.mint == pool.token_a_mint,
+        constraint = user_token_a.owner == owner.key()
+    )]
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_b.mint == pool.token_b_mint,
+        constraint = user_token_b.owner == owner.key()
+    )]
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+// Context for collecting the fees owed to a concentrated position
+#[derive(Accounts)]
+pub struct CollectFees<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = position.pool == pool.key(),
+        constraint = position.owner == owner.key()
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(
+        mut,
+        constraint = tick_array_lower.load()?.pool == pool.key()
+    )]
+    pub tick_array_lower: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        constraint = tick_array_upper.load()?.pool == pool.key()
+    )]
+    pub tick_array_upper: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        constraint = token_a_account.key() == pool.token_a_account
+    )]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_account.key() == pool.token_b_account
+    )]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_a.mint == pool.token_a_mint,
+        constraint = user_token_a.owner == owner.key()
+    )]
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_b.mint == pool.token_b_mint,
+        constraint = user_token_b.owner == owner.key()
+    )]
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_a_program: Interface<'info, TokenInterface>,
+    pub token_b_program: Interface<'info, TokenInterface>,
+}
+
+// Context for closing an empty concentrated position
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    #[account(
+        mut,
+        close = owner,
+        constraint = position.owner == owner.key()
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+// Context for updating the swap fee of a pool
+#[derive(Accounts)]
+pub struct SetPoolFee<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for configuring the volatility-based swap fee of a pool
+#[derive(Accounts)]
+pub struct SetDynamicFee<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for linking a pool to an oracle that guards its swaps
+#[derive(Accounts)]
+pub struct SetOracleGuard<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub oracle_config: Option<Account<'info, OracleConfig>>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for setting a pool's per-swap and per-slot price move limits
+#[derive(Accounts)]
+pub struct SetCircuitBreaker<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for pausing swaps and deposits on a pool
+#[derive(Accounts)]
+pub struct PausePool<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for resuming swaps and deposits on a pool
+#[derive(Accounts)]
+pub struct UnpausePool<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for proposing a new pool authority
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.authority == authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for the proposed authority to take over a pool
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.fee_tier_bps.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.pending_authority == new_authority.key()
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub new_authority: Signer<'info>,
+}
+
+// Context for creating the protocol's fee tier registry
+#[derive(Accounts)]
+pub struct InitializeFeeTierRegistry<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = FeeTierRegistry::SPACE,
+        seeds = [b"fee_tier_registry".as_ref()],
+        bump
+    )]
+    pub fee_tier_registry: Account<'info, FeeTierRegistry>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
-// Account structures for the AMM pool
-#[account]
-pub struct Pool {
-    pub token_a_mint: Pubkey,     // Mint address of token A
-    pub token_b_mint: Pubkey,     // Mint address of token B
-    pub token_a_account: Pubkey,  // Pool's token A account
-    pub token_b_account: Pubkey,  // Pool's token B account
-    pub lp_mint: Pubkey,          // LP token mint address
-    pub authority: Pubkey,        // Authority that can modify the pool
-    pub fee_numerator: u64,       // Numerator for fee calculation (e.g., 3 for 0.3%)
-    pub fee_denominator: u64,     // Denominator for fee calculation (e.g., 1000 for 0.3%)
-    pub bump: u8,                 // PDA bump seed
+// Context for enabling a new fee tier
+#[derive(Accounts)]
+pub struct AddFeeTier<'info> {
+    #[account(
+        mut,
+        seeds = [b"fee_tier_registry".as_ref()],
+        bump = fee_tier_registry.bump,
+        constraint = fee_tier_registry.authority == authority.key()
+    )]
+    pub fee_tier_registry: Account<'info, FeeTierRegistry>,
+
+    pub authority: Signer<'info>,
 }
 
-// Context for initializing a new pool
+// Context for disabling a fee tier for new pools
 #[derive(Accounts)]
-pub struct InitializePool<'info> {
+pub struct RemoveFeeTier<'info> {
     #[account(
-        init, 
-        payer = authority, 
-        space = 8 + std::mem::size_of::<Pool>(),
+        mut,
+        seeds = [b"fee_tier_registry".as_ref()],
+        bump = fee_tier_registry.bump,
+        constraint = fee_tier_registry.authority == authority.key()
+    )]
+    pub fee_tier_registry: Account<'info, FeeTierRegistry>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for creating the pool registry with its first page
+#[derive(Accounts)]
+pub struct InitializePoolRegistry<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<PoolRegistry>(),
+        seeds = [b"pool_registry".as_ref()],
+        bump
+    )]
+    pub pool_registry: Account<'info, PoolRegistry>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = PoolRegistryPage::SPACE,
+        seeds = [b"pool_registry_page".as_ref(), 0u32.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pool_registry_page: Account<'info, PoolRegistryPage>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Context for appending a page to the pool registry
+#[derive(Accounts)]
+pub struct AddPoolRegistryPage<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool_registry".as_ref()],
+        bump = pool_registry.bump,
+    )]
+    pub pool_registry: Account<'info, PoolRegistry>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = PoolRegistryPage::SPACE,
         seeds = [
-            b"pool".as_ref(),
-            token_a_mint.key().as_ref(),
-            token_b_mint.key().as_ref(),
+            b"pool_registry_page".as_ref(),
+            pool_registry.page_count.to_le_bytes().as_ref(),
         ],
         bump
     )]
-    pub pool: Account<'info, Pool>,
-    
+    pub pool_registry_page: Account<'info, PoolRegistryPage>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Context for creating the stats account a referrer's payouts in one token accumulate in
+#[derive(Accounts)]
+pub struct InitializeReferrerStats<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<ReferrerStats>(),
+        seeds = [b"referrer_stats".as_ref(), referrer.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+
+    /// CHECK: Any wallet may be a referrer, it only keys the stats account
+    pub referrer: AccountInfo<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Context for creating a multi-asset pool, its member mints follow as remaining accounts
+#[derive(Accounts)]
+pub struct InitializeMultiPool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MultiPool>(),
+    )]
+    pub pool: Account<'info, MultiPool>,
+
     #[account(
         seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
         bump,
     )]
     /// CHECK: This is a PDA used as the authority for the pool's token accounts
     pub pool_authority: AccountInfo<'info>,
-    
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
-    
+
+    #[account(
+        seeds = [b"fee_tier_registry".as_ref()],
+        bump = fee_tier_registry.bump,
+    )]
+    pub fee_tier_registry: Account<'info, FeeTierRegistry>,
+
     #[account(
         init,
         payer = authority,
-        token::mint = token_a_mint,
-        token::authority = pool_authority,
+        mint::decimals = 9,
+        mint::authority = pool_authority,
+        mint::token_program = token_program,
     )]
-    pub token_a_account: Account<'info, TokenAccount>,
-    
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
     #[account(
         init,
         payer = authority,
-        token::mint = token_b_mint,
+        seeds = [b"locked_lp".as_ref(), pool.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
         token::authority = pool_authority,
+        token::token_program = token_program,
     )]
-    pub token_b_account: Account<'info, TokenAccount>,
-    
+    pub locked_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Context for opening the vault of one multi-asset pool member
+#[derive(Accounts)]
+#[instruction(index: u8)]
+pub struct InitializeMultiPoolVault<'info> {
+    #[account(
+        mut,
+        constraint = pool.authority == authority.key(),
+        constraint = (index as usize) < pool.token_count as usize
+    )]
+    pub pool: Account<'info, MultiPool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        constraint = mint.key() == pool.mints[index as usize]
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
     #[account(
         init,
         payer = authority,
-        mint::decimals = 9,
-        mint::authority = pool_authority,
+        seeds = [b"multi_vault".as_ref(), pool.key().as_ref(), mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = pool_authority,
+        token::token_program = token_program,
     )]
-    pub lp_mint: Account<'info, Mint>,
-    
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
     #[account(mut)]
     pub authority: Signer<'info>,
-    
+
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub rent: Sysvar<'info, Rent>,
 }
 
-// Context for adding liquidity to a pool
+// Context for swapping between two members of a multi-asset pool
 #[derive(Accounts)]
-pub struct AddLiquidity<'info> {
+pub struct SwapMulti<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, MultiPool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
     #[account(
         mut,
-        seeds = [
-            b"pool".as_ref(),
-            pool.token_a_mint.as_ref(),
-            pool.token_b_mint.as_ref(),
-        ],
-        bump = pool.bump,
+        constraint = vault_in.mint == mint_in.key()
     )]
-    pub pool: Account<'info, Pool>,
-    
+    pub vault_in: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = vault_out.mint == mint_out.key()
+    )]
+    pub vault_out: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint_in: InterfaceAccount<'info, Mint>,
+    pub mint_out: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_in.mint == mint_in.key(),
+        constraint = user_token_in.owner == user.key()
+    )]
+    pub user_token_in: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_out.mint == mint_out.key(),
+        constraint = user_token_out.owner == user.key()
+    )]
+    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program_in: Interface<'info, TokenInterface>,
+    pub token_program_out: Interface<'info, TokenInterface>,
+}
+
+// Context for depositing every member of a multi-asset pool, the members follow as
+// remaining accounts
+#[derive(Accounts)]
+pub struct AddMultiLiquidity<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, MultiPool>,
+
     #[account(
         seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token.mint == pool.lp_mint,
+        constraint = user_lp_token.owner == user.key()
+    )]
+    pub user_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"locked_lp".as_ref(), pool.key().as_ref()],
         bump,
     )]
+    pub locked_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+// Context for depositing a single member of a multi-asset pool
+#[derive(Accounts)]
+pub struct AddMultiLiquiditySingleSided<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, MultiPool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
     /// CHECK: This is the PDA that acts as the pool authority
     pub pool_authority: AccountInfo<'info>,
-    
+
     #[account(
         mut,
-        constraint = token_a_account.key() == pool.token_a_account
+        constraint = vault.mint == mint.key()
     )]
-    pub token_a_account: Account<'info, TokenAccount>,
-    
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
     #[account(
         mut,
-        constraint = token_b_account.key() == pool.token_b_account
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_in.mint == mint.key(),
+        constraint = user_token_in.owner == user.key()
+    )]
+    pub user_token_in: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token.mint == pool.lp_mint,
+        constraint = user_lp_token.owner == user.key()
+    )]
+    pub user_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_program_in: Interface<'info, TokenInterface>,
+}
+
+// Context for withdrawing every member of a multi-asset pool, the members follow as
+// remaining accounts
+#[derive(Accounts)]
+pub struct RemoveMultiLiquidity<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, MultiPool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_lp_token.mint == pool.lp_mint,
+        constraint = user_lp_token.owner == user.key()
+    )]
+    pub user_lp_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+// Context for withdrawing a single member of a multi-asset pool
+#[derive(Accounts)]
+pub struct RemoveMultiLiquiditySingleSided<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, MultiPool>,
+
+    #[account(
+        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        bump = pool.authority_bump,
     )]
-    pub token_b_account: Account<'info, TokenAccount>,
-    
+    /// CHECK: This is the PDA that acts as the pool authority
+    pub pool_authority: AccountInfo<'info>,
+
     #[account(
         mut,
-        constraint = lp_mint.key() == pool.lp_mint
+        constraint = vault.mint == mint.key()
     )]
-    pub lp_mint: Account<'info, Mint>,
-    
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
     #[account(
         mut,
-        constraint = user_token_a.mint == pool.token_a_mint,
-        constraint = user_token_a.owner == user.key()
+        constraint = lp_mint.key() == pool.lp_mint
     )]
-    pub user_token_a: Account<'info, TokenAccount>,
-    
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
     #[account(
         mut,
-        constraint = user_token_b.mint == pool.token_b_mint,
-        constraint = user_token_b.owner == user.key()
+        constraint = user_token_out.mint == mint.key(),
+        constraint = user_token_out.owner == user.key()
     )]
-    pub user_token_b: Account<'info, TokenAccount>,
-    
+    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
+
     #[account(
         mut,
         constraint = user_lp_token.mint == pool.lp_mint,
         constraint = user_lp_token.owner == user.key()
     )]
-    pub user_lp_token: Account<'info, TokenAccount>,
-    
+    pub user_lp_token: InterfaceAccount<'info, TokenAccount>,
+
     #[account(mut)]
     pub user: Signer<'info>,
-    
-    pub token_program: Program<'info, Token>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_program_out: Interface<'info, TokenInterface>,
 }
 
-// Context for removing liquidity from a pool
+// Context for opening a launch pool and depositing the tokens for sale, the pool it
+// migrates into is created exactly as `initialize_pool` would create it
 #[derive(Accounts)]
-pub struct RemoveLiquidity<'info> {
+pub struct CreateLaunchPool<'info> {
+    pub pool_accounts: InitializePool<'info>,
+
     #[account(
-        mut,
-        seeds = [
-            b"pool".as_ref(),
-            pool.token_a_mint.as_ref(),
-            pool.token_b_mint.as_ref(),
-        ],
-        bump = pool.bump,
+        init,
+        payer = creator,
+        space = 8 + std::mem::size_of::<LaunchPool>(),
+        seeds = [b"launch_pool".as_ref(), token_mint.key().as_ref()],
+        bump
     )]
-    pub pool: Account<'info, Pool>,
-    
+    pub launch_pool: Account<'info, LaunchPool>,
+
     #[account(
-        seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
+        seeds = [b"launch_authority".as_ref(), launch_pool.key().as_ref()],
         bump,
     )]
-    /// CHECK: This is the PDA that acts as the pool authority
-    pub pool_authority: AccountInfo<'info>,
-    
+    /// CHECK: This is a PDA used as the authority for the launch pool's token accounts
+    pub launch_authority: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_mint.key() != quote_mint.key()
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub quote_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"launch_vault".as_ref(), launch_pool.key().as_ref(), token_mint.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = launch_authority,
+        token::token_program = token_program,
+    )]
+    pub token_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"launch_vault".as_ref(), launch_pool.key().as_ref(), quote_mint.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = launch_authority,
+        token::token_program = quote_token_program,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>,
+
     #[account(
         mut,
-        constraint = token_a_account.key() == pool.token_a_account
+        constraint = creator_token_account.mint == token_mint.key(),
+        constraint = creator_token_account.owner == creator.key()
     )]
-    pub token_a_account: Account<'info, TokenAccount>,
-    
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub quote_token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Context for buying from or selling to a launch pool's bonding curve
+#[derive(Accounts)]
+pub struct TradeLaunchPool<'info> {
+    #[account(mut)]
+    pub launch_pool: Account<'info, LaunchPool>,
+
+    #[account(
+        seeds = [b"launch_authority".as_ref(), launch_pool.key().as_ref()],
+        bump = launch_pool.authority_bump,
+    )]
+    /// CHECK: This is the PDA that acts as the launch authority
+    pub launch_authority: AccountInfo<'info>,
+
     #[account(
         mut,
-        constraint = token_b_account.key() == pool.token_b_account
+        constraint = token_vault.key() == launch_pool.token_vault
     )]
-    pub token_b_account: Account<'info, TokenAccount>,
-    
+    pub token_vault: InterfaceAccount<'info, TokenAccount>,
+
     #[account(
         mut,
-        constraint = lp_mint.key() == pool.lp_mint
+        constraint = quote_vault.key() == launch_pool.quote_vault
     )]
-    pub lp_mint: Account<'info, Mint>,
-    
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>,
+
+    // Writable so the migration can burn the unsold tokens
     #[account(
         mut,
-        constraint = user_token_a.mint == pool.token_a_mint,
-        constraint = user_token_a.owner == user.key()
+        constraint = token_mint.key() == launch_pool.token_mint
     )]
-    pub user_token_a: Account<'info, TokenAccount>,
-    
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = quote_mint.key() == launch_pool.quote_mint
+    )]
+    pub quote_mint: InterfaceAccount<'info, Mint>,
+
     #[account(
         mut,
-        constraint = user_token_b.mint == pool.token_b_mint,
-        constraint = user_token_b.owner == user.key()
+        constraint = user_token_account.mint == launch_pool.token_mint,
+        constraint = user_token_account.owner == user.key()
     )]
-    pub user_token_b: Account<'info, TokenAccount>,
-    
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
     #[account(
         mut,
-        constraint = user_lp_token.mint == pool.lp_mint,
-        constraint = user_lp_token.owner == user.key()
+        constraint = user_quote_account.mint == launch_pool.quote_mint,
+        constraint = user_quote_account.owner == user.key()
     )]
-    pub user_lp_token: Account<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
-    
-    pub token_program: Program<'info, Token>,
-}
+    pub user_quote_account: InterfaceAccount<'info, TokenAccount>,
 
-// Context for swapping tokens
-#[derive(Accounts)]
-pub struct Swap<'info> {
+    // Accounts of the pool the reserves migrate into, only used by the closing buy
     #[account(
-        seeds = [
-            b"pool".as_ref(),
-            pool.token_a_mint.as_ref(),
-            pool.token_b_mint.as_ref(),
-        ],
-        bump = pool.bump,
+        mut,
+        constraint = pool.key() == launch_pool.pool
     )]
     pub pool: Account<'info, Pool>,
-    
+
     #[account(
         seeds = [b"pool_authority".as_ref(), pool.key().as_ref()],
-        bump,
+        bump = pool.authority_bump,
     )]
     /// CHECK: This is the PDA that acts as the pool authority
     pub pool_authority: AccountInfo<'info>,
-    
+
     #[account(
         mut,
-        constraint = token_a_account.key() == pool.token_a_account
+        constraint = pool_token_vault.key() == pool.token_a_account
+            || pool_token_vault.key() == pool.token_b_account,
+        constraint = pool_token_vault.mint == launch_pool.token_mint
     )]
-    pub token_a_account: Account<'info, TokenAccount>,
-    
+    pub pool_token_vault: InterfaceAccount<'info, TokenAccount>,
+
     #[account(
         mut,
-        constraint = token_b_account.key() == pool.token_b_account
+        constraint = pool_quote_vault.key() == pool.token_a_account
+            || pool_quote_vault.key() == pool.token_b_account,
+        constraint = pool_quote_vault.mint == launch_pool.quote_mint
     )]
-    pub token_b_account: Account<'info, TokenAccount>,
-    
+    pub pool_quote_vault: InterfaceAccount<'info, TokenAccount>,
+
     #[account(
         mut,
-        constraint = (user_token_in.mint == pool.token_a_mint || user_token_in.mint == pool.token_b_mint),
-        constraint = user_token_in.owner == user.key()
+        constraint = lp_mint.key() == pool.lp_mint
     )]
-    pub user_token_in: Account<'info, TokenAccount>,
-    
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
     #[account(
         mut,
-        constraint = (user_token_out.mint == pool.token_a_mint || user_token_out.mint == pool.token_b_mint),
-        constraint = user_token_in.mint != user_token_out.mint,
-        constraint = user_token_out.owner == user.key()
+        seeds = [b"locked_lp".as_ref(), pool.key().as_ref()],
+        bump,
     )]
-    pub user_token_out: Account<'info, TokenAccount>,
-    
+    pub locked_lp_token: InterfaceAccount<'info, TokenAccount>,
+
     #[account(mut)]
     pub user: Signer<'info>,
-    
-    pub token_program: Program<'info, Token>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub quote_token_program: Interface<'info, TokenInterface>,
+    pub lp_token_program: Interface<'info, TokenInterface>,
 }
 
 #[error_code]
@@ -534,4 +8500,898 @@ pub enum ErrorCode {
     InvalidAmount,
     #[msg("Slippage tolerance exceeded")]
     SlippageExceeded,
+    #[msg("Amplification coefficient out of range")]
+    InvalidAmp,
+    #[msg("Stable pools require both mints to use the same decimals")]
+    DecimalsMismatch,
+    #[msg("Operation is not supported for this pool type")]
+    InvalidPoolType,
+    #[msg("Amp ramp parameters are invalid")]
+    InvalidRamp,
+    #[msg("A new amp ramp cannot start yet")]
+    RampLocked,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Tick spacing out of range")]
+    InvalidTickSpacing,
+    #[msg("Sqrt price out of range")]
+    InvalidSqrtPrice,
+    #[msg("Tick range is invalid")]
+    InvalidTickRange,
+    #[msg("Tick array does not match the pool or tick")]
+    InvalidTickArray,
+    #[msg("Swap requires a tick array that was not provided")]
+    TickArrayMissing,
+    #[msg("Not enough liquidity to fill the swap")]
+    InsufficientLiquidity,
+    #[msg("Position still holds liquidity or fees")]
+    PositionNotEmpty,
+    #[msg("Route accounts are invalid or do not connect")]
+    InvalidRoute,
+    #[msg("Pool has no liquidity yet")]
+    EmptyPool,
+    #[msg("Observation buffer capacity out of range")]
+    InvalidObservationCapacity,
+    #[msg("Requested window is older than the oldest observation")]
+    ObservationTooOld,
+    #[msg("Pool is locked by an outstanding flash loan")]
+    PoolLocked,
+    #[msg("Flash loan was not repaid with its fee")]
+    FlashLoanNotRepaid,
+    #[msg("Flash loan borrower program is invalid")]
+    InvalidBorrowerProgram,
+    #[msg("Mint uses a Token-2022 extension the pool does not support")]
+    UnsupportedMintExtension,
+    #[msg("Pool is paused")]
+    PoolPaused,
+    #[msg("Fee tier is not enabled or is invalid")]
+    InvalidFeeTier,
+    #[msg("Fee tier registry is full")]
+    FeeTierRegistryFull,
+    #[msg("Token A mint must sort before token B mint")]
+    InvalidMintOrder,
+    #[msg("Pool registry page is not the current page")]
+    InvalidRegistryPage,
+    #[msg("First deposit is below the pool's minimum")]
+    InitialDepositTooSmall,
+    #[msg("User token account is required unless that side is native SOL")]
+    MissingTokenAccount,
+    #[msg("Native SOL needs the temporary wSOL account and the system program")]
+    MissingNativeSolAccounts,
+    #[msg("Referrer token account or stats do not match the swap input")]
+    InvalidReferrer,
+    #[msg("Oracle accounts are missing or do not match the pool")]
+    InvalidOracle,
+    #[msg("Oracle price is stale or unavailable")]
+    OracleStale,
+    #[msg("Swap would move the pool price too far from the oracle price")]
+    OraclePriceDeviation,
+    #[msg("Swap price impact exceeds the pool's per-swap limit")]
+    PriceImpactLimitExceeded,
+    #[msg("Price has moved more than the pool's per-slot limit")]
+    SlotPriceMoveLimitExceeded,
+    #[msg("Pool weights must be positive and each at least the minimum weight")]
+    InvalidWeights,
+    #[msg("Multi-asset pools take between 3 and 8 member mints")]
+    InvalidMultiPoolTokens,
+    #[msg("Accounts do not match the multi-asset pool's members")]
+    InvalidMultiPoolAccounts,
+    #[msg("Not every member vault of the multi-asset pool has been opened")]
+    MultiPoolNotReady,
+    #[msg("Weight schedule must end after it starts and in the future")]
+    InvalidWeightSchedule,
+    #[msg("Only the pool authority can add or remove liquidity during the sale")]
+    SaleInProgress,
+    #[msg("Launch pool parameters or accounts are invalid")]
+    InvalidLaunchPool,
+    #[msg("Launch pool has reached its market cap threshold and no longer trades")]
+    LaunchPoolComplete,
+    #[msg("PMM slippage parameter k must be between 1 and PMM_K_ONE")]
+    InvalidPmmK,
+    #[msg("Circuit breaker limits must be between 1 and 10_000 bps")]
+    InvalidCircuitBreaker,
+    #[msg("Swappers cannot name themselves as their referrer")]
+    SelfReferral,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deterministic xorshift generator, so failures reproduce without a seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        // Mixes magnitudes so both dust and whale amounts are covered
+        fn amount(&mut self) -> u64 {
+            let max = match self.next() % 3 {
+                0 => 1_000_000,
+                1 => 1_000_000_000_000,
+                _ => 1_000_000_000_000_000_000,
+            };
+            1 + self.next() % max
+        }
+    }
+
+    fn withdraw(lp_amount: u64, reserve: u64, lp_supply: u64) -> u64 {
+        (lp_amount as u128 * reserve as u128 / lp_supply as u128) as u64
+    }
+
+    #[test]
+    fn integer_sqrt_is_floor_of_root() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let mut values = vec![0, 1, 2, 3, 4, 15, 16, 17, u64::MAX as u128, u128::MAX];
+        for _ in 0..10_000 {
+            let root = rng.next() as u128;
+            values.push(root * root);
+            values.push(root * root + 2 * root);
+            values.push(((rng.next() as u128) << 64) | rng.next() as u128);
+        }
+
+        for value in values {
+            let root = integer_sqrt(value);
+            assert!(root * root <= value);
+            assert!((root + 1).checked_mul(root + 1).map_or(true, |square| square > value));
+        }
+    }
+
+    #[test]
+    fn first_deposit_locks_minimum_liquidity() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..10_000 {
+            let (amount_a, amount_b) = (rng.amount(), rng.amount());
+            let lp_supply =
+                calculate_constant_product_lp_tokens(0, 0, amount_a, amount_b, 0).unwrap();
+            if lp_supply <= MINIMUM_LIQUIDITY {
+                continue;
+            }
+
+            // The depositor can never redeem the whole pool
+            let lp_tokens = lp_supply - MINIMUM_LIQUIDITY;
+            assert!(withdraw(lp_tokens, amount_a, lp_supply) < amount_a);
+            assert!(withdraw(lp_tokens, amount_b, lp_supply) < amount_b);
+        }
+    }
+
+    #[test]
+    fn donations_cannot_inflate_lp_value() {
+        let mut rng = Rng(0xdead_beef_cafe_f00d);
+        for _ in 0..100_000 {
+            // An attacker seeds the pool, then donates to token A and gets it synced into reserves
+            let (amount_a, amount_b) = (rng.amount(), rng.amount());
+            let lp_supply =
+                calculate_constant_product_lp_tokens(0, 0, amount_a, amount_b, 0).unwrap();
+            if lp_supply <= MINIMUM_LIQUIDITY {
+                continue;
+            }
+            let attacker_lp = lp_supply - MINIMUM_LIQUIDITY;
+            let donation = if rng.next() % 2 == 0 { 0 } else { rng.amount() };
+            let reserve_a = amount_a + donation;
+            let reserve_b = amount_b;
+
+            // A victim deposits in proportion, rounding token B up
+            let victim_a = rng.amount();
+            let victim_b = ((victim_a as u128 * reserve_b as u128 + reserve_a as u128 - 1)
+                / reserve_a as u128) as u64;
+            let victim_lp = calculate_constant_product_lp_tokens(
+                reserve_a,
+                reserve_b,
+                victim_a,
+                victim_b,
+                lp_supply,
+            )
+            .unwrap();
+            let reserve_a = reserve_a + victim_a;
+            let reserve_b = reserve_b + victim_b;
+            let lp_supply = lp_supply + victim_lp;
+
+            // The attacker never gets back more than they put in
+            assert!(withdraw(attacker_lp, reserve_a, lp_supply) <= amount_a + donation);
+            assert!(withdraw(attacker_lp, reserve_b, lp_supply) <= amount_b);
+
+            // The victim loses at most the rounding on one LP token's worth of each side
+            let seed_lp = (lp_supply - victim_lp) as u128;
+            let loss_a = victim_a - withdraw(victim_lp, reserve_a, lp_supply);
+            let loss_b = victim_b - withdraw(victim_lp, reserve_b, lp_supply);
+            assert!((loss_a as u128) * seed_lp < (amount_a + donation) as u128 + seed_lp);
+            assert!((loss_b as u128) * seed_lp < amount_b as u128 + 2 * seed_lp);
+        }
+    }
+
+    #[test]
+    fn apply_deposit_locks_minimum_liquidity_once() {
+        let mut rng = Rng(0x5851_f42d_4c95_7f2d);
+        let pool = Pool::default();
+        for _ in 0..10_000 {
+            let (amount_a, amount_b) = (rng.amount(), rng.amount());
+            let minted = calculate_constant_product_lp_tokens(0, 0, amount_a, amount_b, 0).unwrap();
+            match apply_deposit(&pool, amount_a, amount_b, 0, 0) {
+                Ok(deposit) => {
+                    assert_eq!(deposit.locked_lp, MINIMUM_LIQUIDITY);
+                    assert_eq!(deposit.lp_tokens + deposit.locked_lp, minted);
+                    assert_eq!((deposit.reserve_a, deposit.reserve_b), (amount_a, amount_b));
+                }
+                // Deposits that cannot cover the locked minimum are rejected outright
+                Err(_) => assert!(minted <= MINIMUM_LIQUIDITY),
+            }
+
+            // Later deposits lock nothing
+            let pool = Pool { reserve_a: amount_a, reserve_b: amount_b, ..Pool::default() };
+            if let Ok(deposit) = apply_deposit(&pool, amount_a, amount_b, minted.max(1), 0) {
+                assert_eq!(deposit.locked_lp, 0);
+            }
+        }
+
+        // The pool's minimum first deposit applies to each side
+        let pool = Pool { min_initial_deposit_a: 1_000_000, ..Pool::default() };
+        assert!(apply_deposit(&pool, 999_999, 1_000_000_000, 0, 0).is_err());
+        assert!(apply_deposit(&pool, 1_000_000, 1_000_000_000, 0, 0).is_ok());
+    }
+
+    #[test]
+    fn apply_deposit_prices_against_tracked_reserves() {
+        let mut rng = Rng(0x1405_7b7e_f767_814f);
+        for _ in 0..10_000 {
+            let (reserve_a, reserve_b, lp_supply) = (rng.amount(), rng.amount(), rng.amount());
+            let (amount_a, amount_b) = (rng.amount(), rng.amount());
+            let pool = Pool { reserve_a, reserve_b, ..Pool::default() };
+
+            // Vault balances may hold donations on top of the reserves, but only the
+            // reserves recorded in the pool price the deposit
+            let lp_a = amount_a as u128 * lp_supply as u128 / reserve_a as u128;
+            let lp_b = amount_b as u128 * lp_supply as u128 / reserve_b as u128;
+            let expected = lp_a.min(lp_b);
+            match apply_deposit(&pool, amount_a, amount_b, lp_supply, 0) {
+                Ok(deposit) => {
+                    assert_eq!(deposit.lp_tokens as u128, expected);
+                    assert_eq!(deposit.locked_lp, 0);
+                    assert_eq!(deposit.reserve_a, reserve_a + amount_a);
+                    assert_eq!(deposit.reserve_b, reserve_b + amount_b);
+                }
+                Err(_) => assert!(expected > u64::MAX as u128),
+            }
+        }
+    }
+
+    #[test]
+    fn stable_d_is_preserved_by_swaps() {
+        let mut rng = Rng(0x3c6e_f372_fe94_f82b);
+        for _ in 0..10_000 {
+            let amp = [MIN_AMP, 100, MAX_AMP][(rng.next() % 3) as usize];
+            let pool = Pool { pool_type: PoolType::StableSwap, target_amp: amp, ..Pool::default() };
+
+            // Balances within 2x of each other, the range stable pools trade in
+            let reserve_in = rng.amount();
+            let reserve_out = reserve_in / 2 + rng.next() % (reserve_in + reserve_in / 2 + 1);
+            let amount_in = 1 + rng.next() % (reserve_in / 2 + 1);
+            let amount_out =
+                calculate_swap_output(&pool, true, reserve_in, reserve_out, amount_in, 0).unwrap();
+
+            // D only moves by the solver's one-unit tolerance and the unit kept by the pool
+            let d_before = compute_stable_d(amp, reserve_in, reserve_out).unwrap();
+            let d_after =
+                compute_stable_d(amp, reserve_in + amount_in, reserve_out - amount_out).unwrap();
+            assert!(d_after + 1 >= d_before && d_after <= d_before + 2);
+        }
+    }
+
+    #[test]
+    fn stable_solvers_converge_at_amp_extremes() {
+        let mut rng = Rng(0x6a09_e667_f3bc_c908);
+        for _ in 0..10_000 {
+            for amp in [MIN_AMP, MAX_AMP] {
+                // Up to a 100:1 imbalance, either way round
+                let x = rng.amount();
+                let y = 1 + x / 100 + rng.next() % (x + 1);
+                let (x, y) = if rng.next() % 2 == 1 { (y, x) } else { (x, y) };
+
+                // D sits between the constant product and constant sum invariants
+                let d = compute_stable_d(amp, x, y).unwrap();
+                assert!(2 * integer_sqrt(x as u128 * y as u128) <= d + 1);
+                assert!(d <= x as u128 + y as u128);
+
+                // Solving back for y lands just under it, as D was rounded down
+                let solved = compute_stable_y(amp, x, d).unwrap();
+                assert!(solved <= y as u128 && y as u128 - solved <= y as u128 / 100 + 2);
+            }
+        }
+    }
+
+    #[test]
+    fn stable_y_rounds_in_pools_favor() {
+        let mut rng = Rng(0xbb67_ae85_84ca_a73b);
+        for _ in 0..10_000 {
+            let amp = [MIN_AMP, 100, MAX_AMP][(rng.next() % 3) as usize];
+            let x = rng.amount();
+            let y = x / 2 + rng.next() % (x + x / 2 + 1);
+            let d = compute_stable_d(amp, x, y).unwrap();
+
+            // With the unit swaps add on top, the new balances hold at least D:
+            // 4xy * (Ann * (x + y) + D) >= 4xy * Ann * D + D^3
+            let x = x / 2 + rng.next() % (x + 1);
+            let y = compute_stable_y(amp, x, d).unwrap() + 1;
+            let (x, y, d) = (U256::from(x), U256::from(y), U256::from(d));
+            let ann = U256::from(amp * 2);
+            let four_xy = U256::from(4u8) * x * y;
+            assert!(four_xy * (ann * (x + y) + d) >= four_xy * ann * d + d * d * d);
+        }
+    }
+
+    #[test]
+    fn sqrt_price_at_tick_matches_uniswap_v3() {
+        // getSqrtRatioAtTick results from Uniswap v3 as Q64.96
+        let vectors: [(i32, u128); 13] = [
+            (MIN_TICK, 18_447_090_764_788_882_728),
+            (-250_000, 295_440_463_448_801_648_376_846),
+            (-50_000, 6_504_256_538_020_985_011_912_221_507),
+            (-1_000, 75_364_347_830_767_020_784_054_125_655),
+            (-50, 79_030_349_367_926_598_376_800_521_322),
+            (-1, 79_224_201_403_219_477_170_569_942_574),
+            (0, 79_228_162_514_264_337_593_543_950_336),
+            (1, 79_232_123_823_359_799_118_286_999_568),
+            (50, 79_426_470_787_362_580_746_886_972_461),
+            (1_000, 83_290_069_058_676_223_003_182_343_270),
+            (50_000, 965_075_977_353_221_155_028_623_082_916),
+            (250_000, 21_246_587_762_933_397_357_449_903_968_194_344),
+            (MAX_TICK, 340_275_971_719_517_849_884_101_479_065_584_693_834),
+        ];
+        for (tick, sqrt_ratio_x96) in vectors {
+            // Both round up, so the Q64.64 price is the Q64.96 one rounded up again
+            let expected = (sqrt_ratio_x96 + (1 << 32) - 1) >> 32;
+            assert_eq!(sqrt_price_at_tick(tick), Some(expected));
+        }
+
+        assert_eq!(sqrt_price_at_tick(MIN_TICK), Some(MIN_SQRT_PRICE_X64));
+        assert_eq!(sqrt_price_at_tick(MAX_TICK), Some(MAX_SQRT_PRICE_X64));
+        assert_eq!(sqrt_price_at_tick(MIN_TICK - 1), None);
+        assert_eq!(sqrt_price_at_tick(MAX_TICK + 1), None);
+    }
+
+    #[test]
+    fn tick_and_sqrt_price_round_trip() {
+        let mut rng = Rng(0x510e_527f_ade6_82d1);
+        let mut ticks = vec![MIN_TICK, MIN_TICK + 1, -1, 0, 1, MAX_TICK - 1, MAX_TICK];
+        for _ in 0..1_000 {
+            ticks.push(MIN_TICK + (rng.next() % (MAX_TICK - MIN_TICK) as u64) as i32);
+        }
+
+        for tick in ticks {
+            let sqrt_price_x64 = sqrt_price_at_tick(tick).unwrap();
+            assert_eq!(tick_at_sqrt_price(sqrt_price_x64), tick);
+            // Prices just under a tick's price belong to the tick below
+            if tick > MIN_TICK {
+                assert_eq!(tick_at_sqrt_price(sqrt_price_x64 - 1), tick - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn swap_steps_match_uniswap_v3() {
+        // SwapMath.computeSwapStep cases from Uniswap v3: price 1, 2e18 liquidity and
+        // 1e18 in at a 0.06% fee, with the Q64.96 targets rounded up to Q64.64
+        let to_x64 = |sqrt_ratio_x96: u128| (sqrt_ratio_x96 + (1 << 32) - 1) >> 32;
+        let price = 1u128 << 64;
+        let liquidity = 2_000_000_000_000_000_000;
+        let amount = 1_000_000_000_000_000_000;
+
+        // Capped at the price target
+        let target = to_x64(79_623_317_895_830_914_510_639_640_423);
+        let step =
+            compute_swap_step(price, target, liquidity, amount, 600, 1_000_000, false).unwrap();
+        assert_eq!(step.sqrt_price_next_x64, target);
+        assert_eq!(step.amount_in, 9_975_124_224_178_055);
+        assert_eq!(step.fee_amount, 5_988_667_735_148);
+        assert_eq!(step.amount_out, 9_925_619_580_021_728);
+
+        // Fully spent before the price target
+        let target = to_x64(250_541_448_375_047_931_186_413_801_569);
+        let step =
+            compute_swap_step(price, target, liquidity, amount, 600, 1_000_000, false).unwrap();
+        assert!(step.sqrt_price_next_x64 < target);
+        assert_eq!(step.amount_in, 999_400_000_000_000_000);
+        assert_eq!(step.fee_amount, 600_000_000_000_000);
+        assert_eq!(step.amount_out, 666_399_946_655_997_866);
+    }
+
+    #[test]
+    fn swap_steps_cross_initialized_ticks() {
+        // Positions over [-100, 100) and [-200, 200), selling token A from tick 0
+        let (inner, outer) = (2_000_000_000_000u128, 8_000_000_000_000u128);
+        let amount = 80_000_000_000u128;
+        let price = sqrt_price_at_tick(0).unwrap();
+
+        // The first step stops exactly on the inner position's lower tick
+        let target = sqrt_price_at_tick(-100).unwrap();
+        let liquidity = inner + outer;
+        let first =
+            compute_swap_step(price, target, liquidity, amount, 3_000, 1_000_000, true).unwrap();
+        assert_eq!(first.sqrt_price_next_x64, target);
+        assert_eq!(first.amount_in, get_amount_a_delta(target, price, liquidity, true).unwrap());
+        assert_eq!(first.amount_out, get_amount_b_delta(target, price, liquidity, false).unwrap());
+        assert_eq!(tick_at_sqrt_price(target - 1), -101);
+
+        // Crossing it downwards drops the inner position's liquidity
+        let mut tick = Tick {
+            liquidity_net: inner as i128,
+            fee_growth_outside_a_x64: 7,
+            ..Tick::default()
+        };
+        let liquidity_net = cross_tick(&mut tick, 100, 50, true);
+        assert_eq!(liquidity_net, -(inner as i128));
+        let liquidity = add_liquidity_delta(liquidity, liquidity_net).unwrap();
+        assert_eq!(liquidity, outer);
+
+        // The rest is spent against the outer position before its lower tick
+        let remaining = amount - first.amount_in - first.fee_amount;
+        let outer_lower = sqrt_price_at_tick(-200).unwrap();
+        let second =
+            compute_swap_step(target, outer_lower, liquidity, remaining, 3_000, 1_000_000, true)
+                .unwrap();
+        assert_eq!(second.amount_in + second.fee_amount, remaining);
+        assert!(second.sqrt_price_next_x64 > outer_lower);
+        assert_eq!(tick_at_sqrt_price(second.sqrt_price_next_x64), -174);
+
+        // Crossing back restores the fee growth recorded outside the tick
+        assert_eq!((tick.fee_growth_outside_a_x64, tick.fee_growth_outside_b_x64), (93, 50));
+        assert_eq!(cross_tick(&mut tick, 100, 50, false), inner as i128);
+        assert_eq!((tick.fee_growth_outside_a_x64, tick.fee_growth_outside_b_x64), (7, 0));
+    }
+
+    #[test]
+    fn multi_stable_math_matches_two_token_math() {
+        let mut rng = Rng(0x9b05_688c_2b3e_6c1f);
+        for _ in 0..10_000 {
+            let amp = [MIN_AMP, 100, MAX_AMP][(rng.next() % 3) as usize];
+            let x = rng.amount();
+            let y = x / 2 + rng.next() % (x + x / 2 + 1);
+            let d = compute_stable_d_n(amp, &[x, y]).unwrap();
+            assert_eq!(Some(d), compute_stable_d(amp, x, y));
+
+            let x = x / 2 + rng.next() % (x + 1);
+            assert_eq!(compute_stable_y_n(amp, 1, &[x, y], d), compute_stable_y(amp, x, d));
+        }
+    }
+
+    #[test]
+    fn multi_stable_swaps_preserve_d() {
+        let mut rng = Rng(0x1f83_d9ab_fb41_bd6b);
+        for _ in 0..2_000 {
+            let members = (MAX_MULTI_POOL_TOKENS - MIN_MULTI_POOL_TOKENS + 1) as u64;
+            let token_count = MIN_MULTI_POOL_TOKENS + (rng.next() % members) as usize;
+            let amp = [MIN_AMP, 100, MAX_AMP][(rng.next() % 3) as usize];
+            let mut pool = MultiPool {
+                pool_type: PoolType::StableSwap,
+                amp,
+                token_count: token_count as u8,
+                ..MultiPool::default()
+            };
+            let base = rng.amount();
+            for reserve in pool.reserves[..token_count].iter_mut() {
+                *reserve = base / 2 + rng.next() % (base + base / 2 + 1);
+            }
+
+            let index_in = (rng.next() % token_count as u64) as usize;
+            let offset = 1 + (rng.next() % (token_count as u64 - 1)) as usize;
+            let index_out = (index_in + offset) % token_count;
+            let amount_in = 1 + rng.next() % (pool.reserves[index_out] / 2 + 1);
+            let amount_out =
+                calculate_multi_swap_output(&pool, index_in, index_out, amount_in).unwrap();
+
+            // D only moves by the solver's tolerance and the unit kept by the pool
+            let d_before = compute_stable_d_n(amp, pool.active_reserves()).unwrap();
+            let mut balances = pool.active_reserves().to_vec();
+            balances[index_in] += amount_in;
+            balances[index_out] -= amount_out;
+            let d_after = compute_stable_d_n(amp, &balances).unwrap();
+            assert!(d_after + 1 >= d_before && d_after <= d_before + token_count as u128);
+        }
+    }
+
+    #[test]
+    fn multi_stable_y_rounds_in_pools_favor() {
+        let mut rng = Rng(0x5be0_cd19_137e_2179);
+        for _ in 0..10_000 {
+            let members = (MAX_MULTI_POOL_TOKENS - MIN_MULTI_POOL_TOKENS + 1) as u64;
+            let token_count = MIN_MULTI_POOL_TOKENS + (rng.next() % members) as usize;
+            let amp = [MIN_AMP, 100, MAX_AMP][(rng.next() % 3) as usize];
+
+            // Small balances keep D^(n + 1) within 256 bits at eight members
+            let base = 1 + rng.next() % 1_000_000;
+            let mut balances: Vec<u64> =
+                (0..token_count).map(|_| base / 2 + 1 + rng.next() % (base + 1)).collect();
+            let d = compute_stable_d_n(amp, &balances).unwrap();
+
+            // Move another member, then solve for this one and add the unit swaps keep
+            let index = (rng.next() % token_count as u64) as usize;
+            let offset = 1 + (rng.next() % (token_count as u64 - 1)) as usize;
+            let other = (index + offset) % token_count;
+            balances[other] = balances[other] / 2 + 1 + rng.next() % (balances[other] + 1);
+            balances[index] = compute_stable_y_n(amp, index, &balances, d).unwrap() as u64 + 1;
+
+            // n^n * prod(x) * (Ann * sum(x) + D) >= n^n * prod(x) * Ann * D + D^(n + 1)
+            let n = U256::from(token_count);
+            let ann = U256::from(amp) * n;
+            let d = U256::from(d);
+            let (mut scaled_product, mut d_power, mut sum) = (U256::one(), d, U256::zero());
+            for balance in &balances {
+                scaled_product = scaled_product * n * U256::from(*balance);
+                d_power = d_power * d;
+                sum = sum + U256::from(*balance);
+            }
+            assert!(scaled_product * (ann * sum + d) >= scaled_product * ann * d + d_power);
+        }
+    }
+
+    #[test]
+    fn multi_stable_d_converges_at_the_member_bound() {
+        let mut rng = Rng(0xcbbb_9d5d_c105_9ed8);
+        for _ in 0..5_000 {
+            for amp in [MIN_AMP, MAX_AMP] {
+                // Every member slot filled, with members within 10x of each other
+                let base = rng.amount();
+                let balances: Vec<u64> = (0..MAX_MULTI_POOL_TOKENS)
+                    .map(|_| 1 + base / 10 + rng.next() % (base + 1))
+                    .collect();
+
+                // D sits between n times the geometric mean and the sum
+                let d = compute_stable_d_n(amp, &balances).unwrap();
+                let mean = calculate_geometric_mean(&balances).unwrap();
+                assert!(MAX_MULTI_POOL_TOKENS as u128 * mean as u128 <= d + 1);
+                assert!(d <= balances.iter().map(|balance| *balance as u128).sum::<u128>());
+            }
+        }
+    }
+
+    #[test]
+    fn pmm_quotes_center_on_the_oracle_price() {
+        let mut rng = Rng(0xa54f_f53a_5f1d_36f1);
+        for _ in 0..10_000 {
+            // Oracle prices from 1/16 to about 16 B per A, on a pool balanced at that price
+            let price_x64 = (1u128 << 60) + rng.next() as u128 % (1u128 << 68);
+            let reserve_a = 1_000_000_000 + rng.next() % 1_000_000_000_000_000;
+            let reserve_b = ((reserve_a as u128 * price_x64) >> 64) as u64;
+            let mut pool = Pool {
+                pool_type: PoolType::Pmm,
+                pmm_k: 1 + rng.next() % PMM_K_ONE,
+                pmm_price_x64: price_x64,
+                ..Pool::default()
+            };
+
+            // A balanced pool quotes the oracle price at every k
+            let inverse_x64 = u128::try_from((U256::one() << 128) / U256::from(price_x64)).unwrap();
+            let (price_a, price_b) = spot_prices_x64(&pool, reserve_a, reserve_b, 0).unwrap();
+            assert!(price_a.abs_diff(price_x64) <= price_x64 >> 32);
+            assert!(price_b.abs_diff(inverse_x64) <= inverse_x64 >> 32);
+
+            // and never fills better than the oracle in either direction
+            let amount_a = 1 + rng.next() % (reserve_a / 1_000);
+            let amount_out =
+                calculate_pmm_swap_output(&pool, true, reserve_a, reserve_b, amount_a).unwrap();
+            assert!(amount_out as u128 <= (amount_a as u128 * price_x64) >> 64);
+            let amount_b = 1 + rng.next() % (reserve_b / 1_000);
+            let amount_out =
+                calculate_pmm_swap_output(&pool, false, reserve_b, reserve_a, amount_b).unwrap();
+            assert!((amount_out as u128) <= ((amount_b as u128) << 64) / price_x64);
+
+            // At the smallest k inventory barely moves the price off the oracle
+            pool.pmm_k = 1;
+            let reserve_a = reserve_a / 2 + rng.next() % (reserve_a + 1);
+            let reserve_b = reserve_b / 2 + rng.next() % (reserve_b + 1);
+            let (price_a, _) = spot_prices_x64(&pool, reserve_a, reserve_b, 0).unwrap();
+            assert!(price_a.abs_diff(price_x64) <= price_x64 >> 18);
+        }
+    }
+
+    #[test]
+    fn pmm_without_an_oracle_price_trades_like_constant_product() {
+        // A pool with no oracle linked clears any price it read before
+        let mut pool = Pool {
+            pool_type: PoolType::Pmm,
+            pmm_k: PMM_K_ONE / 2,
+            pmm_price_x64: 1 << 64,
+            ..Pool::default()
+        };
+        refresh_pmm_price(&mut pool, None, None, (6, 6), 0).unwrap();
+        assert_eq!(pool.pmm_price_x64, 0);
+
+        // Stale or missing prices are stored as zero, which prices on the reserves alone
+        let constant_product = Pool::default();
+        let mut rng = Rng(0x152f_ecd8_f70e_5939);
+        for _ in 0..10_000 {
+            let reserve_in = rng.amount() + 1;
+            let reserve_out = rng.amount() + 1;
+            pool.pmm_k = 1 + rng.next() % PMM_K_ONE;
+            let a_to_b = rng.next() % 2 == 0;
+
+            // The PMM output rounds the new reserve up, so it may keep one more unit
+            let amount_in = 1 + rng.next() % reserve_in;
+            let pmm_out =
+                calculate_swap_output(&pool, a_to_b, reserve_in, reserve_out, amount_in, 0);
+            let cp_out = calculate_swap_output(
+                &constant_product,
+                a_to_b,
+                reserve_in,
+                reserve_out,
+                amount_in,
+                0,
+            )
+            .unwrap();
+            assert!(pmm_out.unwrap() <= cp_out && cp_out <= pmm_out.unwrap() + 1);
+
+            let amount_out = rng.next() % reserve_out;
+            assert_eq!(
+                calculate_swap_input(&pool, a_to_b, reserve_in, reserve_out, amount_out, 0),
+                calculate_swap_input(
+                    &constant_product,
+                    a_to_b,
+                    reserve_in,
+                    reserve_out,
+                    amount_out,
+                    0
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn sale_runs_only_on_bootstrapping_pools() {
+        let mut pool = Pool {
+            pool_type: PoolType::Weighted,
+            weight_start_ts: 100,
+            weight_end_ts: 200,
+            ..Pool::default()
+        };
+        assert!(!pool.sale_in_progress(150));
+
+        pool.liquidity_bootstrapping = true;
+        assert!(pool.sale_in_progress(50));
+        assert!(pool.sale_in_progress(199));
+        assert!(!pool.sale_in_progress(200));
+    }
+
+    #[test]
+    fn launch_trades_never_round_trip_for_profit() {
+        let mut rng = Rng(0x629a_292a_367c_d507);
+        for _ in 0..10_000 {
+            let (quote_reserve, token_reserve) = (rng.amount(), rng.amount());
+            let quote_in = rng.amount();
+            let tokens_out =
+                calculate_launch_output(quote_reserve, token_reserve, quote_in).unwrap();
+
+            // Selling the tokens straight back returns at most what was paid
+            let quote_out = calculate_launch_output(
+                token_reserve - tokens_out,
+                quote_reserve + quote_in,
+                tokens_out,
+            )
+            .unwrap();
+            assert!(quote_out <= quote_in);
+        }
+    }
+
+    #[test]
+    fn launch_threshold_is_reachable() {
+        let mut rng = Rng(0x9159_015a_3070_dd17);
+        for _ in 0..2_000 {
+            // Any curve create_launch_pool accepts, with at least the deposit in virtual tokens
+            let virtual_token_reserve = rng.amount();
+            let token_reserve = 1 + rng.next() % virtual_token_reserve;
+            let launch_pool = LaunchPool {
+                token_supply: token_reserve + rng.next() % (virtual_token_reserve + 1),
+                virtual_token_reserve,
+                virtual_quote_reserve: rng.amount(),
+                token_reserve,
+                ..LaunchPool::default()
+            };
+            let market_cap = launch_pool.market_cap().unwrap();
+            let max_market_cap = launch_pool.max_market_cap().unwrap();
+            assert!(max_market_cap > market_cap);
+            let threshold = market_cap + 1 + rng.next() as u128 % (max_market_cap - market_cap);
+
+            let buy = |quote_in: u64| {
+                let tokens_out = calculate_launch_output(
+                    launch_pool.virtual_quote_reserve,
+                    launch_pool.virtual_token_reserve,
+                    quote_in,
+                )
+                .unwrap();
+                let after = LaunchPool {
+                    virtual_quote_reserve: launch_pool.virtual_quote_reserve + quote_in,
+                    virtual_token_reserve: launch_pool.virtual_token_reserve - tokens_out,
+                    ..launch_pool.clone()
+                };
+                (tokens_out, after.market_cap().unwrap())
+            };
+
+            // The smallest buy that reaches the threshold stays within the deposit
+            let (mut low, mut high) = (1, u64::MAX - launch_pool.virtual_quote_reserve);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if buy(mid).1 >= threshold {
+                    high = mid;
+                } else {
+                    low = mid + 1;
+                }
+            }
+            let (tokens_out, market_cap) = buy(low);
+            assert!(tokens_out <= launch_pool.token_reserve && market_cap >= threshold);
+        }
+    }
+
+    #[test]
+    fn observations_interpolate_between_neighbours() {
+        let mut buffer = ObservationBuffer {
+            pool: Pubkey::default(),
+            capacity: 4,
+            next_index: 0,
+            observations: Vec::new(),
+            bump: 0,
+        };
+        buffer.record(100, 1_000, 5_000);
+        buffer.record(200, 3_000, 6_000);
+        // Only the first observation in a second is kept
+        buffer.record(200, 9_000, 9_000);
+
+        // Exact hits return the stored values, the live accumulators included
+        assert_eq!(buffer.cumulative_prices_at(100, 300, 7_000, 8_000), Some((1_000, 5_000)));
+        assert_eq!(buffer.cumulative_prices_at(200, 300, 7_000, 8_000), Some((3_000, 6_000)));
+        assert_eq!(buffer.cumulative_prices_at(300, 300, 7_000, 8_000), Some((7_000, 8_000)));
+
+        // Between two points the accumulators move linearly
+        assert_eq!(buffer.cumulative_prices_at(150, 300, 7_000, 8_000), Some((2_000, 5_500)));
+        assert_eq!(buffer.cumulative_prices_at(275, 300, 7_000, 8_000), Some((6_000, 7_500)));
+
+        // Nothing is known before the oldest observation
+        assert_eq!(buffer.cumulative_prices_at(99, 300, 7_000, 8_000), None);
+    }
+
+    #[test]
+    fn observations_stay_chronological_after_wrapping() {
+        let mut buffer = ObservationBuffer {
+            pool: Pubkey::default(),
+            capacity: 3,
+            next_index: 0,
+            observations: Vec::new(),
+            bump: 0,
+        };
+        for timestamp in 1..=5 {
+            buffer.record(timestamp, timestamp as u128 * 10, timestamp as u128 * 20);
+        }
+
+        // The two oldest observations were overwritten in place
+        let timestamps: Vec<i64> = buffer.chronological().map(|o| o.timestamp).collect();
+        assert_eq!(timestamps, vec![3, 4, 5]);
+        assert_eq!(buffer.latest().unwrap().timestamp, 5);
+        assert_eq!(buffer.cumulative_prices_at(2, 6, 60, 120), None);
+        assert_eq!(buffer.cumulative_prices_at(3, 6, 60, 120), Some((30, 60)));
+        assert_eq!(buffer.cumulative_prices_at(4, 6, 60, 120), Some((40, 80)));
+    }
+
+    #[test]
+    fn circuit_breaker_measures_from_the_slot_start_price() {
+        let price = |percent: u128| (1u128 << 64) * percent / 100;
+        let mut pool = Pool {
+            max_price_impact_bps: 500,
+            max_slot_price_move_bps: 800,
+            ..Pool::default()
+        };
+
+        // The first swap in a slot records the price the slot started at
+        pool.check_circuit_breaker(Some(price(100)), Some(price(104)), 7).unwrap();
+        assert_eq!((pool.slot_start_slot, pool.slot_start_price_x64), (7, price(100)));
+
+        // Later swaps in the slot are measured against that start price, not their own
+        pool.check_circuit_breaker(Some(price(104)), Some(price(108)), 7).unwrap();
+        assert_eq!(pool.slot_start_price_x64, price(100));
+        assert_eq!(
+            pool.check_circuit_breaker(Some(price(108)), Some(price(110)), 7).unwrap_err(),
+            ErrorCode::SlotPriceMoveLimitExceeded.into()
+        );
+
+        // The next slot starts again from the price before its first swap
+        pool.check_circuit_breaker(Some(price(108)), Some(price(110)), 8).unwrap();
+        assert_eq!((pool.slot_start_slot, pool.slot_start_price_x64), (8, price(108)));
+        assert_eq!(
+            pool.check_circuit_breaker(Some(price(108)), Some(price(114)), 8).unwrap_err(),
+            ErrorCode::PriceImpactLimitExceeded.into()
+        );
+    }
+
+    #[test]
+    fn circuit_breaker_zero_limits_are_disabled() {
+        let price = |percent: u128| (1u128 << 64) * percent / 100;
+        let mut pool = Pool::default();
+        pool.check_circuit_breaker(Some(price(100)), Some(price(300)), 7).unwrap();
+        pool.check_circuit_breaker(Some(price(300)), Some(price(10)), 7).unwrap();
+
+        // Each limit can be turned on without the other
+        pool.max_price_impact_bps = 500;
+        pool.check_circuit_breaker(Some(price(100)), Some(price(104)), 7).unwrap();
+        pool.check_circuit_breaker(Some(price(104)), Some(price(108)), 7).unwrap();
+        pool.check_circuit_breaker(Some(price(108)), Some(price(112)), 7).unwrap();
+        assert!(pool.check_circuit_breaker(Some(price(112)), Some(price(120)), 7).is_err());
+    }
+
+    #[test]
+    fn dynamic_fee_decays_linearly_up_to_its_cap() {
+        let mut pool = Pool {
+            fee_numerator: 30,
+            fee_denominator: 10_000,
+            dynamic_fee_enabled: true,
+            volatility_fee_factor_bps: 1_000,
+            max_dynamic_fee_bps: 100,
+            volatility_decay_period: 100,
+            volatility_bps: 1_000,
+            volatility_updated_ts: 1_000,
+            ..Pool::default()
+        };
+
+        // 1_000 bps of volatility adds 100 bps to the 30 bps base fee, over the 100 bps cap
+        assert_eq!((pool.volatility_at(1_000), pool.swap_fee_numerator(1_000)), (1_000, 100));
+        assert_eq!((pool.volatility_at(1_050), pool.swap_fee_numerator(1_050)), (500, 80));
+        assert_eq!((pool.volatility_at(1_075), pool.swap_fee_numerator(1_075)), (250, 55));
+        assert_eq!((pool.volatility_at(1_100), pool.swap_fee_numerator(1_100)), (0, 30));
+        assert_eq!((pool.volatility_at(5_000), pool.swap_fee_numerator(5_000)), (0, 30));
+
+        pool.dynamic_fee_enabled = false;
+        assert_eq!(pool.swap_fee_numerator(1_000), 30);
+    }
+
+    #[test]
+    fn volatility_accumulates_up_to_its_clamp() {
+        let mut pool = Pool {
+            reserve_a: 1_000_000,
+            reserve_b: 1_000_000,
+            dynamic_fee_enabled: true,
+            max_volatility_bps: 5_000,
+            volatility_decay_period: 100,
+            volatility_bps: 1_000,
+            volatility_updated_ts: 1_000,
+            ..Pool::default()
+        };
+
+        // A 25% price move adds 2_500 bps to what is left of the decayed accumulator
+        pool.record_volatility((800_000, 1_000_000), 1_050).unwrap();
+        assert_eq!((pool.volatility_bps, pool.volatility_updated_ts), (3_000, 1_050));
+
+        // A doubling would add 10_000 bps, the accumulator stops at its clamp
+        pool.record_volatility((500_000, 1_000_000), 1_050).unwrap();
+        assert_eq!(pool.volatility_bps, 5_000);
+
+        // Pools without dynamic fees do not track volatility
+        pool.dynamic_fee_enabled = false;
+        pool.record_volatility((500_000, 1_000_000), 1_060).unwrap();
+        assert_eq!((pool.volatility_bps, pool.volatility_updated_ts), (5_000, 1_050));
+    }
+
+    #[test]
+    fn equal_weights_match_constant_product() {
+        let mut rng = Rng(0x0123_4567_89ab_cdef);
+        let half = WEIGHT_ONE / 2;
+        for _ in 0..10_000 {
+            let (reserve_in, reserve_out, amount_in) = (rng.amount(), rng.amount(), rng.amount());
+            let product = reserve_in as u128 * reserve_out as u128;
+            let constant_product =
+                reserve_out - (product / (reserve_in as u128 + amount_in as u128)) as u64;
+
+            // The weighted output rounds for the pool, but only by the pow error bound
+            let weighted =
+                calculate_weighted_swap_output(reserve_in, reserve_out, half, half, amount_in)
+                    .unwrap();
+            assert!(weighted <= constant_product);
+            assert!(constant_product - weighted <= (constant_product >> 30) + 2);
+
+            let invariant =
+                calculate_weighted_invariant(reserve_in, reserve_out, half, half).unwrap();
+            let root = integer_sqrt(product) as u64;
+            assert!(invariant <= root && root - invariant <= (root >> 30) + 2);
+        }
+    }
 }